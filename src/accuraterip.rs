@@ -0,0 +1,309 @@
+//! Checksums of ripped tracks as computed by AccurateRip, which can be
+//! compared against other people's rips of the same pressing.
+
+use std::io::Read;
+
+use anyhow::bail;
+use tracing::debug;
+
+use crate::{cddb, mb::DEFAULT_USER_AGENT, source::LEAD_IN_SECTORS};
+
+const ROOT_URL: &str = "http://www.accuraterip.com/accuraterip";
+
+/// Stereo frames in one sector.
+const FRAMES_PER_SECTOR: u32 = 588;
+
+/// Frames at the start of the first track and the end of the last track that
+/// AccurateRip leaves out, because drives disagree on them depending on
+/// their read offset.
+const SKIPPED_FRAMES: u32 = 5 * FRAMES_PER_SECTOR;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Checksums {
+    /// The original checksum, which drops the high bits of each frame's
+    /// product and so misses some errors in the right channel.
+    pub v1: u32,
+    pub v2: u32,
+}
+
+/// Computes both checksum versions in one pass over a track's interleaved
+/// stereo samples. `last_track` is the last audio track of the disc.
+pub fn compute(samples: &[i16], track_num: u32, last_track: u32) -> Checksums {
+    let frame_count = (samples.len() / 2) as u32;
+    // Frames are numbered from 1, and the checksum covers `check_from` to
+    // `check_to` inclusive.
+    let check_from = if track_num == 1 { SKIPPED_FRAMES } else { 0 };
+    let check_to = if track_num == last_track {
+        frame_count.saturating_sub(SKIPPED_FRAMES)
+    } else {
+        frame_count
+    };
+
+    let mut checksums = Checksums { v1: 0, v2: 0 };
+    for (position, frame) in (1..).zip(samples.chunks_exact(2)) {
+        if position < check_from || position > check_to {
+            continue;
+        }
+        // The left channel goes in the low 16 bits, as in the raw PCM data.
+        let value = (frame[0] as u16 as u32) | ((frame[1] as u16 as u32) << 16);
+        let product = value as u64 * position as u64;
+        checksums.v1 = checksums.v1.wrapping_add(product as u32);
+        checksums.v2 = checksums
+            .v2
+            .wrapping_add(product as u32)
+            .wrapping_add((product >> 32) as u32);
+    }
+    checksums
+}
+
+/// How a track's checksums compare against the AccurateRip database.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Verification {
+    /// Matches rips of one or more pressings; `confidence` is the number of
+    /// submissions with the same checksum.
+    Match { confidence: u32, version: u32 },
+    /// The disc is in the database, but no submission for this track
+    /// matches. `submissions` is the number of submissions there are.
+    Mismatch { submissions: u32 },
+}
+
+/// One pressing's checksums in the database, by track.
+pub struct Pressing {
+    tracks: Vec<Entry>,
+}
+
+struct Entry {
+    confidence: u8,
+    checksum: u32,
+}
+
+/// The AccurateRip disc IDs of a disc, from the start sector of each audio
+/// track followed by the lead-out sector. The sectors are logical block
+/// addresses, starting from 0 at the start of the first track.
+///
+/// The third ID is the disc's CDDB ID, computed from the same sectors; for
+/// enhanced CDs this leaves out the data track.
+pub fn accuraterip_ids(toc: &[u32]) -> (u32, u32, u32) {
+    let (&lead_out, offsets) = toc.split_last().expect("TOC has no lead-out");
+
+    let id1 = offsets.iter().sum::<u32>().wrapping_add(lead_out);
+    let id2 = (1..)
+        .zip(offsets.iter().chain([&lead_out]))
+        .fold(0u32, |sum, (position, &offset)| {
+            sum.wrapping_add(offset.max(1).wrapping_mul(position))
+        });
+
+    // CDDB counts the two-second lead-in as part of each offset.
+    let with_lead_in: Vec<u32> = offsets
+        .iter()
+        .map(|offset| offset + LEAD_IN_SECTORS)
+        .collect();
+    let cddb_id = cddb::disc_id(&with_lead_in, lead_out + LEAD_IN_SECTORS);
+
+    (id1, id2, cddb_id)
+}
+
+fn lookup_url(toc: &[u32]) -> String {
+    let (id1, id2, cddb_id) = accuraterip_ids(toc);
+    format!(
+        "{}/{:x}/{:x}/{:x}/dBAR-{:03}-{:08x}-{:08x}-{:08x}.bin",
+        ROOT_URL,
+        id1 & 0xf,
+        (id1 >> 4) & 0xf,
+        (id1 >> 8) & 0xf,
+        toc.len() - 1,
+        id1,
+        id2,
+        cddb_id
+    )
+}
+
+/// Fetches the checksums submitted for the disc with the given TOC (see
+/// `accuraterip_ids`), or `None` if nobody has submitted the disc.
+pub fn lookup(toc: &[u32]) -> anyhow::Result<Option<Vec<Pressing>>> {
+    let url = lookup_url(toc);
+    debug!("AccurateRip lookup: {}", url);
+    let response = match ureq::get(&url).set("User-Agent", DEFAULT_USER_AGENT).call() {
+        Ok(response) => response,
+        Err(ureq::Error::Status(404, _)) => return Ok(None),
+        Err(error) => return Err(error.into()),
+    };
+    let mut data = Vec::new();
+    response.into_reader().read_to_end(&mut data)?;
+    parse_response(&data, toc.len() - 1).map(Some)
+}
+
+/// Parses the database's binary format: one chunk per pressing, each a
+/// header of the track count and the three disc IDs, then the confidence,
+/// checksum and (unused) offset-finding checksum of each track. Numbers are
+/// little-endian.
+fn parse_response(mut data: &[u8], track_count: usize) -> anyhow::Result<Vec<Pressing>> {
+    const HEADER_LEN: usize = 13;
+    const ENTRY_LEN: usize = 9;
+
+    let u32_at =
+        |bytes: &[u8], at: usize| u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap());
+
+    let mut pressings = Vec::new();
+    while !data.is_empty() {
+        let chunk_tracks = data[0] as usize;
+        let chunk_len = HEADER_LEN + chunk_tracks * ENTRY_LEN;
+        if data.len() < chunk_len {
+            bail!("AccurateRip response is truncated");
+        }
+        let (chunk, rest) = data.split_at(chunk_len);
+        data = rest;
+        if chunk_tracks != track_count {
+            continue;
+        }
+        let tracks = chunk[HEADER_LEN..]
+            .chunks_exact(ENTRY_LEN)
+            .map(|entry| Entry {
+                confidence: entry[0],
+                checksum: u32_at(entry, 1),
+            })
+            .collect();
+        pressings.push(Pressing { tracks });
+    }
+    Ok(pressings)
+}
+
+/// Compares the checksums of the track at `track_index` (0 for the first
+/// track in the TOC) against every pressing.
+pub fn verify(pressings: &[Pressing], track_index: usize, checksums: Checksums) -> Verification {
+    let mut best: Option<(u32, u32)> = None;
+    let mut submissions = 0;
+    for entry in pressings
+        .iter()
+        .filter_map(|pressing| pressing.tracks.get(track_index))
+    {
+        let confidence = entry.confidence as u32;
+        submissions += confidence;
+        let version = if entry.checksum == checksums.v2 {
+            2
+        } else if entry.checksum == checksums.v1 {
+            1
+        } else {
+            continue;
+        };
+        if best.is_none_or(|(best_confidence, _)| best_confidence < confidence) {
+            best = Some((confidence, version));
+        }
+    }
+    match best {
+        Some((confidence, version)) => Verification::Match {
+            confidence,
+            version,
+        },
+        None => Verification::Mismatch { submissions },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Samples of `frame_count` frames that each have the value 1, so that
+    /// the checksum is the sum of the positions it covers.
+    fn ones(frame_count: u32) -> Vec<i16> {
+        [1, 0].repeat(frame_count as usize)
+    }
+
+    fn position_sum(from: u32, to: u32) -> u32 {
+        (from..=to).sum()
+    }
+
+    #[test]
+    fn skipped_frames() {
+        let frames = 3 * SKIPPED_FRAMES;
+        let samples = ones(frames);
+        assert_eq!(compute(&samples, 2, 3).v1, position_sum(1, frames));
+        assert_eq!(compute(&samples, 1, 3).v1, position_sum(2940, frames));
+        assert_eq!(compute(&samples, 3, 3).v1, position_sum(1, frames - 2940));
+        assert_eq!(
+            compute(&samples, 1, 1).v1,
+            position_sum(2940, frames - 2940)
+        );
+        assert_eq!(compute(&ones(100), 1, 1), Checksums { v1: 0, v2: 0 });
+    }
+
+    #[test]
+    fn checksum_versions() {
+        // The left sample is the low half of the frame's value.
+        assert_eq!(compute(&[0x1234, 0x5678], 2, 3).v1, 0x5678_1234);
+
+        // 0xffffffff at position 2 is 0x1_ffff_fffe, whose high bits only
+        // v2 keeps.
+        assert_eq!(
+            compute(&[0, 0, -1, -1], 2, 3),
+            Checksums {
+                v1: 0xffff_fffe,
+                v2: 0xffff_ffff,
+            }
+        );
+    }
+
+    #[test]
+    fn disc_ids() {
+        let toc = [0, 22365, 39615];
+        assert_eq!(accuraterip_ids(&toc), (0x0000f21c, 0x00027ef8, 0x05021002));
+        assert_eq!(
+            lookup_url(&toc),
+            "http://www.accuraterip.com/accuraterip/c/1/2/dBAR-002-0000f21c-00027ef8-05021002.bin"
+        );
+    }
+
+    /// A response chunk with the given confidence and checksum for each
+    /// track.
+    fn chunk(tracks: &[(u8, u32)]) -> Vec<u8> {
+        let mut chunk = vec![tracks.len() as u8];
+        // The disc IDs, which are not checked.
+        chunk.extend([0; 12]);
+        for &(confidence, checksum) in tracks {
+            chunk.push(confidence);
+            chunk.extend(checksum.to_le_bytes());
+            chunk.extend([0; 4]);
+        }
+        chunk
+    }
+
+    #[test]
+    fn parse_pressings() {
+        let data = [
+            chunk(&[(3, 0x11), (3, 0x22)]),
+            chunk(&[(1, 0x33), (1, 0x44), (1, 0x55)]),
+            chunk(&[(5, 0x66), (5, 0x77)]),
+        ]
+        .concat();
+        // The chunk with a different track count is left out.
+        let pressings = parse_response(&data, 2).unwrap();
+        assert_eq!(pressings.len(), 2);
+
+        assert_eq!(
+            verify(&pressings, 0, Checksums { v1: 0, v2: 0x11 }),
+            Verification::Match {
+                confidence: 3,
+                version: 2,
+            }
+        );
+        assert_eq!(
+            verify(&pressings, 1, Checksums { v1: 0x77, v2: 0 }),
+            Verification::Match {
+                confidence: 5,
+                version: 1,
+            }
+        );
+        assert_eq!(
+            verify(&pressings, 0, Checksums { v1: 0x33, v2: 0x33 }),
+            Verification::Mismatch { submissions: 8 }
+        );
+    }
+
+    #[test]
+    fn truncated_response() {
+        let data = chunk(&[(3, 0x11), (3, 0x22)]);
+        assert!(parse_response(&data[..data.len() - 1], 2).is_err());
+        assert!(parse_response(&data[..5], 2).is_err());
+        assert!(parse_response(&[], 2).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,107 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use dialoguer::Confirm;
+use serde::Deserialize;
+use tracing::error;
+
+use crate::{config::Config, Cli, PathSanitizer};
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct BatchFile {
+    #[serde(default)]
+    disc: Vec<BatchDisc>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct BatchDisc {
+    /// Drive to read this disc from. If unset, the user is asked to insert
+    /// the disc into the default drive.
+    device: Option<String>,
+    release_id: String,
+    output_path: PathBuf,
+}
+
+/// Rips every disc listed in the batch file at `path`, then prints a summary.
+///
+/// A disc that fails to rip is reported and skipped; the batch only fails as
+/// a whole if any disc did.
+pub fn run(
+    args: &Cli,
+    config: &Config,
+    path_sanitizer: &PathSanitizer,
+    disc_device: &str,
+    path: &Path,
+) -> anyhow::Result<()> {
+    let contents = std::fs::read_to_string(path)
+        .with_context(|| format!("failed to read {}", path.display()))?;
+    let batch: BatchFile =
+        toml::from_str(&contents).with_context(|| format!("failed to parse {}", path.display()))?;
+
+    let mut results = Vec::with_capacity(batch.disc.len());
+    for (index, disc) in batch.disc.iter().enumerate() {
+        println!();
+        println!(
+            "=== Disc {} of {}: {} ===",
+            index + 1,
+            batch.disc.len(),
+            disc.release_id
+        );
+
+        let device = match &disc.device {
+            Some(device) => device.as_str(),
+            None => {
+                let ready = Confirm::new()
+                    .with_prompt(format!("Insert disc: {}\nReady?", disc.release_id))
+                    .default(true)
+                    .interact()?;
+                if !ready {
+                    results.push(Err(anyhow::anyhow!("skipped by user")));
+                    continue;
+                }
+                disc_device
+            }
+        };
+
+        let result = crate::rip(
+            args,
+            config,
+            path_sanitizer,
+            device,
+            &expand_home(&disc.output_path),
+            Some(&disc.release_id),
+        );
+        if let Err(error) = &result {
+            error!("Failed to rip {}: {:#}", disc.release_id, error);
+        }
+        results.push(result);
+    }
+
+    println!();
+    println!("=== Batch summary ===");
+    let mut failures = 0;
+    for (disc, result) in batch.disc.iter().zip(&results) {
+        match result {
+            Ok(()) => println!("  OK      {}", disc.release_id),
+            Err(error) => {
+                failures += 1;
+                println!("  FAILED  {}: {:#}", disc.release_id, error);
+            }
+        }
+    }
+
+    if failures > 0 {
+        anyhow::bail!("{} of {} discs failed", failures, results.len());
+    }
+    Ok(())
+}
+
+/// Expands a leading `~` to the user's home directory.
+fn expand_home(path: &Path) -> PathBuf {
+    match (path.strip_prefix("~"), std::env::var_os("HOME")) {
+        (Ok(rest), Some(home)) => PathBuf::from(home).join(rest),
+        _ => path.to_path_buf(),
+    }
+}
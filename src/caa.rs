@@ -0,0 +1,130 @@
+use std::{collections::HashMap, io::Read, path::PathBuf};
+
+use serde::Deserialize;
+
+use crate::{
+    mb::{or_any_scalar, DEFAULT_USER_AGENT},
+    PathSanitizer,
+};
+
+const ROOT_URL: &str = "https://coverartarchive.org";
+
+pub struct CoverArt {
+    pub mime_type: String,
+    pub data: Vec<u8>,
+}
+
+impl CoverArt {
+    pub fn extension(&self) -> &str {
+        match self.mime_type.as_str() {
+            "image/png" => "png",
+            "image/gif" => "gif",
+            _ => "jpg",
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Image {
+    #[serde(deserialize_with = "or_any_scalar")]
+    pub id: Option<String>,
+    pub types: Vec<String>,
+    pub image: String,
+}
+
+impl Image {
+    pub fn extension(&self) -> &str {
+        self.image
+            .rsplit_once('.')
+            .map(|(_, extension)| extension)
+            .filter(|extension| !extension.contains('/'))
+            .unwrap_or("jpg")
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct ImageList {
+    images: Vec<Image>,
+}
+
+/// Sends a GET request, treating a 404 response as `None`.
+fn get(url: &str) -> anyhow::Result<Option<ureq::Response>> {
+    match ureq::get(url).set("User-Agent", DEFAULT_USER_AGENT).call() {
+        Ok(response) => Ok(Some(response)),
+        Err(ureq::Error::Status(404, _)) => Ok(None),
+        Err(error) => Err(error.into()),
+    }
+}
+
+/// Downloads an image, or returns `None` if it does not exist.
+pub fn fetch(url: &str) -> anyhow::Result<Option<CoverArt>> {
+    let Some(response) = get(url)? else {
+        return Ok(None);
+    };
+
+    let mime_type = response.content_type().to_string();
+    let mut data = Vec::new();
+    response.into_reader().read_to_end(&mut data)?;
+
+    Ok(Some(CoverArt { mime_type, data }))
+}
+
+/// Fetches the front cover of a release, or `None` if it has none.
+pub fn fetch_front(release_id: &str) -> anyhow::Result<Option<CoverArt>> {
+    fetch(&format!("{}/release/{}/front", ROOT_URL, release_id))
+}
+
+/// Lists every image the Cover Art Archive has for a release.
+pub fn list_images(release_id: &str) -> anyhow::Result<Vec<Image>> {
+    let Some(response) = get(&format!("{}/release/{}", ROOT_URL, release_id))? else {
+        return Ok(Vec::new());
+    };
+
+    let mut jd = serde_json::Deserializer::from_reader(response.into_reader());
+    let list: ImageList = serde_path_to_error::deserialize(&mut jd)?;
+    Ok(list.images)
+}
+
+/// Chooses a path, relative to the album directory, for each image.
+///
+/// Images are named after their type (`front.jpg`, `back.jpg`, ...) or, with
+/// `by_index`, after their Cover Art Archive ID. Repeated names get a numeric
+/// suffix.
+pub fn image_paths(
+    images: Vec<Image>,
+    dir: Option<&str>,
+    by_index: bool,
+    path_sanitizer: &PathSanitizer,
+) -> Vec<(PathBuf, Image)> {
+    let base = dir.map(|dir| PathBuf::from(path_sanitizer.map(dir)));
+    let mut seen: HashMap<String, u32> = HashMap::new();
+
+    images
+        .into_iter()
+        .map(|image| {
+            let stem = if by_index {
+                image.id.clone().unwrap_or_default()
+            } else {
+                image
+                    .types
+                    .first()
+                    .map(|image_type| image_type.to_lowercase())
+                    .unwrap_or_else(|| "other".to_string())
+            };
+            let count = seen.entry(stem.clone()).or_default();
+            *count += 1;
+            let stem = if *count > 1 {
+                format!("{}-{}", stem, count)
+            } else {
+                stem
+            };
+
+            let file_name = path_sanitizer.map(&format!("{}.{}", stem, image.extension()));
+            let path = match &base {
+                Some(base) => base.join(file_name),
+                None => PathBuf::from(file_name),
+            };
+            (path, image)
+        })
+        .collect()
+}
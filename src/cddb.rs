@@ -0,0 +1,168 @@
+use std::fmt::Write;
+
+use crate::{
+    mb::{Media, Release},
+    tags, CD_SECTORS_PER_SECOND,
+};
+
+/// Longest value written on a single xmcd line; longer values are continued
+/// on repeated `KEYWORD=` lines, as the format requires.
+const MAX_VALUE_LEN: usize = 200;
+
+/// Computes the CDDB/freedb disc ID of a disc from the start sector of each
+/// track and the lead-out sector, counting the two-second lead-in as
+/// MusicBrainz TOCs do.
+pub fn disc_id(offsets: &[u32], lead_out: u32) -> u32 {
+    fn digit_sum(mut n: u32) -> u32 {
+        let mut sum = 0;
+        while n > 0 {
+            sum += n % 10;
+            n /= 10;
+        }
+        sum
+    }
+
+    let checksum: u32 = offsets
+        .iter()
+        .map(|offset| digit_sum(offset / CD_SECTORS_PER_SECOND))
+        .sum();
+    let first = offsets.first().copied().unwrap_or(0);
+    let length = lead_out / CD_SECTORS_PER_SECOND - first / CD_SECTORS_PER_SECOND;
+    (checksum % 0xff) << 24 | length << 8 | offsets.len() as u32
+}
+
+/// Formats a CDDB xmcd database entry for `media` from its MusicBrainz data.
+pub fn format_xmcd_entry(release: &Release, media: &Media, disc_id: u32) -> String {
+    let mut out = String::new();
+
+    out.push_str("# xmcd\n#\n# Track frame offsets:\n");
+    // Prefer the MusicBrainz disc that hashes to the same CDDB ID, as other
+    // pressings attached to the medium may have slightly different offsets.
+    let disc = media
+        .discs
+        .iter()
+        .find(|disc| disc.freedb_id() == disc_id)
+        .or_else(|| media.discs.first());
+    if let Some(disc) = disc {
+        for offset in &disc.offsets {
+            writeln!(out, "#\t{}", offset).unwrap();
+        }
+        writeln!(
+            out,
+            "#\n# Disc length: {} seconds",
+            disc.sectors / CD_SECTORS_PER_SECOND
+        )
+        .unwrap();
+    }
+    writeln!(
+        out,
+        "#\n# Revision: 0\n# Submitted via: ripoff {}\n#",
+        env!("CARGO_PKG_VERSION")
+    )
+    .unwrap();
+
+    let album_artist = release.artist_string();
+
+    push_field(&mut out, "DISCID", &format!("{:08x}", disc_id));
+    let mut title = release.title.clone();
+    if release.media.len() > 1 {
+        write!(title, " (Disc {})", media.position).unwrap();
+    }
+    push_field(&mut out, "DTITLE", &format!("{} / {}", album_artist, title));
+    push_field(
+        &mut out,
+        "DYEAR",
+        &release
+            .release_year()
+            .map(|year| year.to_string())
+            .unwrap_or_default(),
+    );
+    push_field(&mut out, "DGENRE", "");
+
+    for (index, track) in media.tracks.iter().enumerate() {
+        let artist = track.artist_string();
+        let track_title = tags::resolve_track_title(track, index as u32 + 1);
+        let title = if artist.is_empty() || artist == album_artist {
+            track_title.into_owned()
+        } else {
+            format!("{} / {}", artist, track_title)
+        };
+        push_field(&mut out, &format!("TTITLE{}", index), &title);
+    }
+
+    push_field(
+        &mut out,
+        "EXTD",
+        &format!("MusicBrainz release {}", release.id),
+    );
+    for (index, track) in media.tracks.iter().enumerate() {
+        push_field(
+            &mut out,
+            &format!("EXTT{}", index),
+            &format!("MusicBrainz recording {}", track.recording.id),
+        );
+    }
+    push_field(&mut out, "PLAYORDER", "");
+
+    out
+}
+
+/// Appends a `KEYWORD=value` line, escaping the value and splitting it over
+/// several lines if it is too long.
+fn push_field(out: &mut String, keyword: &str, value: &str) {
+    let mut line = String::new();
+    let mut line_len = 0;
+    let mut buf = [0; 4];
+    for c in value.chars() {
+        let escaped: &str = match c {
+            '\\' => "\\\\",
+            '\n' => "\\n",
+            '\t' => "\\t",
+            c => c.encode_utf8(&mut buf),
+        };
+        let width = escaped.chars().count();
+        // Keep escape sequences on a single line.
+        if line_len + width > MAX_VALUE_LEN {
+            writeln!(out, "{}={}", keyword, line).unwrap();
+            line.clear();
+            line_len = 0;
+        }
+        line_len += width;
+        line.push_str(escaped);
+    }
+    writeln!(out, "{}={}", keyword, line).unwrap();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn known_disc_id() {
+        // The disc in AccurateRip's dBAR-002-0000f21c-00027ef8-05021002.bin.
+        assert_eq!(disc_id(&[150, 22515], 39765), 0x05021002);
+    }
+
+    #[test]
+    fn escaped_values() {
+        let mut out = String::new();
+        push_field(&mut out, "DTITLE", "A\\B\tC\nD");
+        assert_eq!(out, "DTITLE=A\\\\B\\tC\\nD\n");
+    }
+
+    #[test]
+    fn long_values_continue() {
+        let mut out = String::new();
+        push_field(&mut out, "EXTD", &"a".repeat(250));
+        assert_eq!(
+            out,
+            format!("EXTD={}\nEXTD={}\n", "a".repeat(200), "a".repeat(50))
+        );
+
+        // An escape sequence that would straddle the limit moves to the next
+        // line whole.
+        let mut out = String::new();
+        push_field(&mut out, "EXTD", &format!("{}\n", "a".repeat(199)));
+        assert_eq!(out, format!("EXTD={}\nEXTD=\\n\n", "a".repeat(199)));
+    }
+}
@@ -0,0 +1,141 @@
+use std::{
+    fmt::Write as _,
+    fs::File,
+    hash::Hasher as _,
+    io::{self, Read},
+    path::Path,
+};
+
+use anyhow::{bail, Context};
+use md5::{Digest, Md5};
+use sha1::Sha1;
+use sha2::Sha256;
+use twox_hash::XxHash64;
+
+#[derive(Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum ManifestFormat {
+    /// MD5 of each file, in the format of GNU `md5sum` (checksums.md5)
+    Md5,
+    /// SHA-1 of each file, in the format of GNU `sha1sum` (checksums.sha1)
+    Sha1,
+    /// SHA-256 of each file, in the format of GNU `sha256sum`
+    /// (checksums.sha256)
+    Sha256,
+    /// XXH64 of each file, in the format of `xxhsum` (checksums.xxh64)
+    #[value(name = "xxhash")]
+    XxHash,
+    /// CRC32 of each file, in Simple File Verification format (checksums.sfv)
+    Sfv,
+}
+
+impl ManifestFormat {
+    pub fn file_name(self) -> &'static str {
+        match self {
+            Self::Md5 => "checksums.md5",
+            Self::Sha1 => "checksums.sha1",
+            Self::Sha256 => "checksums.sha256",
+            Self::XxHash => "checksums.xxh64",
+            Self::Sfv => "checksums.sfv",
+        }
+    }
+}
+
+/// Reads the MD5 signature of the unencoded audio from a FLAC file's
+/// STREAMINFO block, without decoding the audio frames.
+pub fn read_flac_md5(path: &Path) -> anyhow::Result<[u8; 16]> {
+    let tag = metaflac::Tag::read_from_path(path)
+        .with_context(|| format!("failed to read FLAC metadata from {:?}", path))?;
+    let stream_info = tag
+        .get_streaminfo()
+        .with_context(|| format!("{:?} has no STREAMINFO block", path))?;
+    let md5: [u8; 16] = stream_info
+        .md5
+        .as_slice()
+        .try_into()
+        .with_context(|| format!("{:?} has a malformed MD5 signature", path))?;
+    if md5 == [0; 16] {
+        bail!("{:?} was encoded without an MD5 signature", path);
+    }
+    Ok(md5)
+}
+
+/// Formats an MD5 signature as lowercase hex, as `metaflac --show-md5sum`
+/// does.
+pub fn md5_hex(md5: &[u8; 16]) -> String {
+    md5.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Writes a manifest of the checksums of `file_names` into `album_dir`,
+/// hashing the files as they are on disk.
+pub fn write_manifest(
+    album_dir: &Path,
+    file_names: &[String],
+    format: ManifestFormat,
+) -> anyhow::Result<()> {
+    let mut manifest = String::new();
+    if format == ManifestFormat::Sfv {
+        writeln!(
+            manifest,
+            "; Generated by ripoff {}",
+            env!("CARGO_PKG_VERSION")
+        )
+        .unwrap();
+    }
+    for file_name in file_names {
+        let path = album_dir.join(file_name);
+        let mut file = File::open(&path).with_context(|| format!("failed to open {:?}", path))?;
+        let hex = match format {
+            ManifestFormat::Md5 => file_digest::<Md5>(&mut file),
+            ManifestFormat::Sha1 => file_digest::<Sha1>(&mut file),
+            ManifestFormat::Sha256 => file_digest::<Sha256>(&mut file),
+            ManifestFormat::XxHash => file_xxh64(&mut file),
+            ManifestFormat::Sfv => {
+                let crc =
+                    file_crc(&mut file).with_context(|| format!("failed to read {:?}", path))?;
+                writeln!(manifest, "{} {:08X}", file_name, crc).unwrap();
+                continue;
+            }
+        }
+        .with_context(|| format!("failed to read {:?}", path))?;
+        // Two spaces mark the file as hashed in text mode, as the GNU tools
+        // and xxhsum print by default.
+        writeln!(manifest, "{}  {}", hex, file_name).unwrap();
+    }
+
+    let path = album_dir.join(format.file_name());
+    std::fs::write(&path, manifest).with_context(|| format!("failed to write {:?}", path))
+}
+
+fn file_digest<D: Digest + io::Write>(file: &mut File) -> io::Result<String> {
+    let mut hasher = D::new();
+    io::copy(file, &mut hasher)?;
+    Ok(hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect())
+}
+
+fn file_xxh64(file: &mut File) -> io::Result<String> {
+    let mut hasher = XxHash64::with_seed(0);
+    let mut buffer = vec![0; 64 * 1024];
+    loop {
+        let len = file.read(&mut buffer)?;
+        if len == 0 {
+            return Ok(format!("{:016x}", hasher.finish()));
+        }
+        hasher.write(&buffer[..len]);
+    }
+}
+
+fn file_crc(file: &mut File) -> io::Result<u32> {
+    let mut hasher = crc32fast::Hasher::new();
+    let mut buffer = vec![0; 64 * 1024];
+    loop {
+        let len = file.read(&mut buffer)?;
+        if len == 0 {
+            return Ok(hasher.finalize());
+        }
+        hasher.update(&buffer[..len]);
+    }
+}
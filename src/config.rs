@@ -0,0 +1,153 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Context};
+use clap::{parser::ValueSource, ArgMatches, ValueEnum};
+use directories::ProjectDirs;
+use serde::Deserialize;
+
+use crate::{encode::OutputFormat, paranoia::parse_paranoia_mode, template::Template, Cli};
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+pub struct Config {
+    /// Defaults for the command-line options of the same names.
+    pub format: Option<String>,
+    pub compression_level: Option<u32>,
+    pub read_offset: Option<i32>,
+    pub paranoia_mode: Option<String>,
+    pub filename_template: Option<String>,
+    pub dirname_template: Option<String>,
+    /// Overridden by both `--eject` and `--no-eject`.
+    pub eject: Option<bool>,
+    pub mb_root_url: Option<String>,
+
+    /// Paranoia mode overrides, keyed by track number.
+    #[serde(default)]
+    pub track_paranoia: BTreeMap<String, String>,
+}
+
+impl Config {
+    /// `config.toml` in the platform's config directory for ripoff, which on
+    /// Linux is `$XDG_CONFIG_HOME/ripoff` or `~/.config/ripoff`.
+    pub fn default_path() -> Option<PathBuf> {
+        let dirs = ProjectDirs::from("", "", "ripoff")?;
+        Some(dirs.config_dir().join("config.toml"))
+    }
+
+    /// Loads the config file at `path`, or at the default path if not given.
+    /// A missing file at the default path is the same as an empty one.
+    pub fn load(path: Option<&Path>) -> anyhow::Result<Self> {
+        let path = match path {
+            Some(path) => path.to_path_buf(),
+            None => match Self::default_path().filter(|path| path.exists()) {
+                Some(path) => path,
+                None => return Ok(Self::default()),
+            },
+        };
+        let contents = std::fs::read_to_string(&path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        let config: Self = toml::from_str(&contents)
+            .with_context(|| format!("failed to parse {}", path.display()))?;
+        config
+            .validate()
+            .with_context(|| format!("invalid config in {}", path.display()))?;
+        Ok(config)
+    }
+
+    /// Fills in the options that were not given on the command line or in the
+    /// environment from the config file.
+    pub fn apply_to(&self, args: &mut Cli, matches: &ArgMatches) -> anyhow::Result<()> {
+        let unset = |id: &str| {
+            matches!(
+                matches.value_source(id),
+                None | Some(ValueSource::DefaultValue)
+            )
+        };
+
+        if let Some(format) = self.format.as_deref().filter(|_| unset("format")) {
+            args.format = parse_format(format)?;
+        }
+        if let Some(level) = self
+            .compression_level
+            .filter(|_| unset("compression_level"))
+        {
+            args.compression_level = level;
+        }
+        if let Some(offset) = self.read_offset.filter(|_| unset("read_offset")) {
+            args.read_offset = offset;
+        }
+        if let Some(mode) = self
+            .paranoia_mode
+            .as_ref()
+            .filter(|_| unset("paranoia_mode"))
+        {
+            args.paranoia_mode = mode.clone();
+        }
+        if let Some(template) = self
+            .filename_template
+            .as_deref()
+            .filter(|_| unset("filename_template"))
+        {
+            args.filename_template = Some(Template::parse(template)?);
+        }
+        if let Some(template) = self
+            .dirname_template
+            .as_deref()
+            .filter(|_| unset("dirname_template"))
+        {
+            args.dirname_template = Some(Template::parse_dirname(template)?);
+        }
+        if let Some(eject) = self.eject.filter(|_| unset("eject") && unset("no_eject")) {
+            args.eject = eject;
+        }
+        if let Some(root_url) = self.mb_root_url.as_ref().filter(|_| unset("mb_root_url")) {
+            args.mb_root_url = root_url.clone();
+        }
+        Ok(())
+    }
+
+    fn validate(&self) -> anyhow::Result<()> {
+        if let Some(format) = &self.format {
+            parse_format(format)?;
+        }
+        if let Some(level) = self.compression_level {
+            if level > 8 {
+                bail!("compression_level must be from 0 to 8, not {}", level);
+            }
+        }
+        if let Some(mode) = &self.paranoia_mode {
+            parse_paranoia_mode(mode).context("invalid paranoia_mode")?;
+        }
+        if let Some(template) = &self.filename_template {
+            Template::parse(template).context("invalid filename_template")?;
+        }
+        if let Some(template) = &self.dirname_template {
+            Template::parse_dirname(template).context("invalid dirname_template")?;
+        }
+        for (track, mode) in &self.track_paranoia {
+            track.parse::<u32>().with_context(|| {
+                format!("invalid track number in [track_paranoia]: {:?}", track)
+            })?;
+            parse_paranoia_mode(mode)
+                .with_context(|| format!("invalid paranoia mode for track {}", track))?;
+        }
+        Ok(())
+    }
+
+    pub fn track_paranoia_mode(&self, track_num: u32) -> Option<&str> {
+        self.track_paranoia
+            .iter()
+            .find(|(track, _)| track.parse() == Ok(track_num))
+            .map(|(_, mode)| mode.as_str())
+    }
+}
+
+fn parse_format(format: &str) -> anyhow::Result<OutputFormat> {
+    match OutputFormat::from_str(format, true) {
+        Ok(format) => Ok(format),
+        Err(error) => bail!("invalid format {:?}: {}", format, error),
+    }
+}
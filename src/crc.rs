@@ -0,0 +1,114 @@
+use std::{collections::BTreeMap, path::Path};
+
+use anyhow::Context;
+use crc32fast::Hasher;
+
+/// Largest read offset tried when reconciling a CRC mismatch, in samples per
+/// channel. Drive read offsets are normally well within five sectors.
+const MAX_OFFSET: usize = 5 * 588;
+
+/// CRC32 of a track's audio, computed over the little-endian PCM data the
+/// same way as the "copy CRC" of other rippers.
+pub fn track_crc(samples: &[i16]) -> u32 {
+    crc32fast::hash(&pcm_bytes(samples))
+}
+
+fn pcm_bytes(samples: &[i16]) -> Vec<u8> {
+    samples.iter().flat_map(|s| s.to_le_bytes()).collect()
+}
+
+/// Reads a reference CRC file with one `track_num,crc32_hex` line per track.
+pub fn load_reference(path: &Path) -> anyhow::Result<BTreeMap<u32, u32>> {
+    let contents = std::fs::read_to_string(path)
+        .with_context(|| format!("failed to read {}", path.display()))?;
+    let mut crcs = BTreeMap::new();
+    for (line_num, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let parse = || -> Option<(u32, u32)> {
+            let (track, crc) = line.split_once(',')?;
+            let crc = crc.trim();
+            let crc = crc
+                .strip_prefix("0x")
+                .or_else(|| crc.strip_prefix("0X"))
+                .unwrap_or(crc);
+            Some((
+                track.trim().parse().ok()?,
+                u32::from_str_radix(crc, 16).ok()?,
+            ))
+        };
+        let (track, crc) = parse().with_context(|| {
+            format!(
+                "{}:{}: expected `track_num,crc32_hex`",
+                path.display(),
+                line_num + 1
+            )
+        })?;
+        crcs.insert(track, crc);
+    }
+    Ok(crcs)
+}
+
+/// Searches for a read offset that makes the track match `reference_crc`.
+///
+/// Only the audio of this track is available, so the samples shifted in at
+/// either end are assumed to be silence. This holds for most tracks that
+/// start and end with a gap, but not for tracks that run into each other.
+///
+/// A positive offset means the reference starts that many samples later in
+/// the audio than this rip.
+pub fn find_offset(samples: &[i16], channels: usize, reference_crc: u32) -> Option<i32> {
+    let bytes = pcm_bytes(samples);
+    let frame_len = 2 * channels;
+    let total_len = bytes.len();
+    let max_offset = MAX_OFFSET.min((total_len / frame_len).saturating_sub(1));
+    let whole_crc = crc32fast::hash(&bytes);
+    let zero_frame = vec![0; frame_len];
+
+    // Zero-padded CRCs are built from prefix CRCs with the identity
+    // crc(a || b) = shift(crc(a), len(b)) ^ crc(b), where shift() is linear.
+    let mut prefix = Hasher::new();
+    let mut zeros = Hasher::new();
+    let mut zero_crcs = vec![Hasher::new().finalize()];
+    for offset in 1..=max_offset {
+        let shift_len = offset * frame_len;
+        prefix.update(&bytes[shift_len - frame_len..shift_len]);
+        zeros.update(&zero_frame);
+        let zero_crc = zeros.clone().finalize();
+        zero_crcs.push(zero_crc);
+
+        // crc(bytes[shift_len..] || zeros)
+        let crc =
+            shift(prefix.clone().finalize(), total_len) ^ shift(whole_crc, shift_len) ^ zero_crc;
+        if crc == reference_crc {
+            return Some(offset as i32);
+        }
+    }
+
+    let mut prefix = Hasher::new();
+    prefix.update(&bytes[..total_len - max_offset * frame_len]);
+    for offset in (1..=max_offset).rev() {
+        let shift_len = offset * frame_len;
+        if offset < max_offset {
+            prefix.update(&bytes[total_len - shift_len - frame_len..total_len - shift_len]);
+        }
+
+        // crc(zeros || bytes[..total_len - shift_len])
+        let crc = shift(zero_crcs[offset], total_len - shift_len) ^ prefix.clone().finalize();
+        if crc == reference_crc {
+            return Some(-(offset as i32));
+        }
+    }
+
+    None
+}
+
+/// The CRC of a message followed by `len` zero bytes, minus the CRC of the
+/// zero bytes themselves.
+fn shift(crc: u32, len: usize) -> u32 {
+    let mut hasher = Hasher::new_with_initial_len(crc, 0);
+    hasher.combine(&Hasher::new_with_initial_len(0, len as u64));
+    hasher.finalize()
+}
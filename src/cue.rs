@@ -0,0 +1,59 @@
+use std::fmt::Write;
+
+use crate::{
+    mb::{Media, Release},
+    tags,
+};
+
+/// Formats a CUE sheet for `media`, with one `FILE` per track. `files` maps
+/// each ripped track number to its file name in the album directory.
+/// `htoa_file` is the audio hidden in the pre-gap of track 1, if it was
+/// ripped.
+///
+/// Every track starts at the beginning of its own file, so each `INDEX 01` is
+/// at 00:00:00.
+pub fn format_cue_sheet(
+    release: &Release,
+    media: &Media,
+    htoa_file: Option<&str>,
+    files: &[(u32, String)],
+) -> String {
+    // Many CUE parsers only recognize UTF-8 with a byte order mark.
+    let mut out = String::from("\u{feff}");
+
+    if let Some(year) = release.release_year() {
+        writeln!(out, "REM DATE {}", year).unwrap();
+    }
+    writeln!(out, "REM COMMENT \"ripoff {}\"", env!("CARGO_PKG_VERSION")).unwrap();
+    writeln!(out, "PERFORMER {}", quote(&release.artist_string())).unwrap();
+    writeln!(out, "TITLE {}", quote(&release.title)).unwrap();
+
+    for (track_num, file_name) in files {
+        // The hidden track is the pre-gap of track 1, so track 1 starts in
+        // its file with INDEX 00.
+        let pregap_file = htoa_file.filter(|_| *track_num == 1);
+        writeln!(out, "FILE {} WAVE", quote(pregap_file.unwrap_or(file_name))).unwrap();
+        writeln!(out, "  TRACK {:02} AUDIO", track_num).unwrap();
+        if let Some(track) = media.tracks.get(*track_num as usize - 1) {
+            let title = tags::resolve_track_title(track, *track_num);
+            writeln!(out, "    TITLE {}", quote(&title)).unwrap();
+            writeln!(out, "    PERFORMER {}", quote(&track.artist_string())).unwrap();
+            if let Some(isrc) = track.recording.isrcs.first() {
+                writeln!(out, "    ISRC {}", isrc).unwrap();
+            }
+        }
+        if pregap_file.is_some() {
+            writeln!(out, "    INDEX 00 00:00:00").unwrap();
+            writeln!(out, "FILE {} WAVE", quote(file_name)).unwrap();
+        }
+        writeln!(out, "    INDEX 01 00:00:00").unwrap();
+    }
+
+    out
+}
+
+/// Quotes a CUE sheet value. The format has no escapes, so double quotes in
+/// the value are replaced with single quotes.
+fn quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('"', "'"))
+}
@@ -0,0 +1,30 @@
+use crate::CD_SAMPLE_RATE;
+
+/// Time constants of the standard CD pre-emphasis curve, in seconds.
+const T1: f64 = 50e-6;
+const T2: f64 = 15e-6;
+
+/// Undoes 50/15 µs pre-emphasis in place on interleaved 16-bit samples.
+///
+/// This is the bilinear transform of the analog de-emphasis filter
+/// `(1 + s*T2) / (1 + s*T1)`: unity gain at DC, falling to -10.5 dB at high
+/// frequencies.
+pub fn deemphasize(samples: &mut [i16], channels: usize) {
+    let k = 2.0 * CD_SAMPLE_RATE as f64;
+    let a0 = 1.0 + T1 * k;
+    let b0 = (1.0 + T2 * k) / a0;
+    let b1 = (1.0 - T2 * k) / a0;
+    let a1 = (1.0 - T1 * k) / a0;
+
+    for channel in 0..channels {
+        let mut x1 = 0.0;
+        let mut y1 = 0.0;
+        for sample in samples.iter_mut().skip(channel).step_by(channels) {
+            let x0 = *sample as f64;
+            let y0 = b0 * x0 + b1 * x1 - a1 * y1;
+            *sample = y0.round().clamp(i16::MIN as f64, i16::MAX as f64) as i16;
+            x1 = x0;
+            y1 = y0;
+        }
+    }
+}
@@ -0,0 +1,86 @@
+use std::path::Path;
+
+use ::discid::DiscId;
+use anyhow::{anyhow, bail, Context};
+
+use crate::mb;
+
+/// Reads a disc's TOC from a file instead of from the drive.
+///
+/// The file holds a single line of comma-separated numbers:
+/// `disc_id,first_track,last_track,leadout,offset1,offset2,...`, with one
+/// offset per track. The lead-out and track offsets are in sectors and
+/// include the two-second lead-in, as in a MusicBrainz TOC. The disc ID is
+/// checked against the one computed from the offsets.
+pub fn load_toc_file(path: &Path) -> anyhow::Result<DiscId> {
+    let contents = std::fs::read_to_string(path)
+        .with_context(|| format!("failed to read {}", path.display()))?;
+    let line = contents.trim();
+    let (disc_id, numbers) = line
+        .split_once(',')
+        .with_context(|| format!("{}: expected `disc_id,first_track,...`", path.display()))?;
+    let numbers: Vec<i32> = numbers
+        .split(',')
+        .map(|number| number.trim().parse())
+        .collect::<Result<_, _>>()
+        .with_context(|| format!("{}: invalid number in TOC", path.display()))?;
+
+    let [first_track, last_track, leadout, offsets @ ..] = numbers.as_slice() else {
+        bail!(
+            "{}: expected first track, last track and lead-out after the disc ID",
+            path.display()
+        );
+    };
+    if !(1..=99).contains(first_track) || last_track < first_track || *last_track > 99 {
+        bail!(
+            "{}: invalid track range {}-{}",
+            path.display(),
+            first_track,
+            last_track
+        );
+    }
+    let track_count = (last_track - first_track + 1) as usize;
+    if offsets.len() != track_count {
+        bail!(
+            "{}: expected {} track offsets, found {}",
+            path.display(),
+            track_count,
+            offsets.len()
+        );
+    }
+
+    // libdiscid takes the lead-out followed by the track offsets.
+    let mut put_offsets = vec![*leadout];
+    put_offsets.extend_from_slice(offsets);
+    let disc = DiscId::put(*first_track, &put_offsets)
+        .map_err(|error| anyhow!("{}: invalid TOC: {}", path.display(), error))?;
+
+    let disc_id = disc_id.trim();
+    if disc.id() != disc_id {
+        bail!(
+            "{}: disc ID {} does not match the TOC, which has ID {}",
+            path.display(),
+            disc_id,
+            disc.id()
+        );
+    }
+    Ok(disc)
+}
+
+/// Rebuilds a disc's TOC from its MusicBrainz disc ID entry, for
+/// `--test-disc-id`. MusicBrainz offsets include the lead-in, as in a TOC
+/// file.
+pub fn from_musicbrainz(disc: &mb::Disc) -> anyhow::Result<DiscId> {
+    let mut put_offsets = vec![disc.sectors as i32];
+    put_offsets.extend(disc.offsets.iter().map(|&offset| offset as i32));
+    let toc = DiscId::put(1, &put_offsets)
+        .map_err(|error| anyhow!("disc ID {} has an invalid TOC: {}", disc.id, error))?;
+    if toc.id() != disc.id {
+        bail!(
+            "disc ID {} does not match its TOC on MusicBrainz, which has ID {}",
+            disc.id,
+            toc.id()
+        );
+    }
+    Ok(toc)
+}
@@ -0,0 +1,97 @@
+use std::{
+    ffi::CString,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use anyhow::{bail, Context};
+use cdparanoia::{CdromDrive, Verbosity};
+
+/// Names of optical drive devices in /dev, followed by a number (or nothing,
+/// for the /dev/cdrom symlink).
+const DEVICE_PREFIXES: &[&str] = &["cdrom", "sr", "dvd"];
+
+/// Identifies the drive behind `disc_device` as "vendor model firmware", as
+/// reported by the kernel in sysfs.
+pub fn identification(disc_device: &str) -> Option<String> {
+    // Resolve symlinks such as /dev/cdrom -> /dev/sr0.
+    let device = Path::new(disc_device).canonicalize().ok()?;
+    let sysfs_dir = Path::new("/sys/block")
+        .join(device.file_name()?)
+        .join("device");
+
+    let fields: Vec<String> = ["vendor", "model", "rev"]
+        .iter()
+        .filter_map(|field| std::fs::read_to_string(sysfs_dir.join(field)).ok())
+        .map(|value| value.trim().to_string())
+        .filter(|value| !value.is_empty())
+        .collect();
+    if fields.is_empty() {
+        None
+    } else {
+        Some(fields.join(" "))
+    }
+}
+
+/// Opens the tray of `disc_device` with the system `eject` command.
+pub fn eject(disc_device: &str) -> anyhow::Result<()> {
+    let status = Command::new("eject")
+        .arg(disc_device)
+        .status()
+        .context("failed to run eject")?;
+    if !status.success() {
+        bail!("eject exited with {}", status);
+    }
+    Ok(())
+}
+
+/// Prints every optical drive found in /dev, with its model and firmware.
+pub fn list_devices() -> anyhow::Result<()> {
+    let mut devices: Vec<PathBuf> = std::fs::read_dir("/dev")
+        .context("failed to read /dev")?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(is_device_name)
+        })
+        .collect();
+    devices.sort();
+
+    if devices.is_empty() {
+        println!("No CD drives found. Check that the drive is connected and has a disc loaded.");
+        return Ok(());
+    }
+    for device in devices {
+        let name = device.to_string_lossy();
+        let mut line = format!(
+            "{}: {}",
+            name,
+            identification(&name).unwrap_or_else(|| "unknown drive".to_string())
+        );
+        if let Some(target) = device
+            .canonicalize()
+            .ok()
+            .filter(|target| *target != device)
+        {
+            line += &format!(" (-> {})", target.display());
+        }
+        let opens = CString::new(name.as_bytes())
+            .ok()
+            .and_then(|c_name| CdromDrive::identify(&c_name, Verbosity::LogIt))
+            .is_some();
+        if !opens {
+            line += " [cannot be opened; is a disc loaded?]";
+        }
+        println!("{}", line);
+    }
+    Ok(())
+}
+
+fn is_device_name(name: &str) -> bool {
+    DEVICE_PREFIXES.iter().any(|prefix| {
+        name.strip_prefix(prefix)
+            .is_some_and(|rest| rest.chars().all(|c| c.is_ascii_digit()))
+    })
+}
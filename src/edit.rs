@@ -0,0 +1,57 @@
+//! Editing track titles and artists before ripping, for fixing mistakes in
+//! the MusicBrainz data without having to retag the files afterwards.
+
+use dialoguer::{theme::ColorfulTheme, Input};
+
+use crate::{mb::Media, tags};
+
+/// A track's title and artist as MusicBrainz had them, before they were
+/// edited.
+pub struct MbOriginal {
+    pub track_num: u32,
+    pub title: String,
+    pub artist: String,
+}
+
+/// Asks for a new title and artist for each track of `medium`, starting from
+/// the current ones. Returns the original values of the tracks that were
+/// changed.
+pub fn edit_tracks(medium: &mut Media) -> anyhow::Result<Vec<MbOriginal>> {
+    let console_theme = ColorfulTheme::default();
+    let mut originals = Vec::new();
+
+    for (track_num, track) in (1..).zip(&mut medium.tracks) {
+        let title = tags::resolve_track_title(track, track_num).into_owned();
+        let artist = track.artist_string();
+
+        let new_title: String = Input::with_theme(&console_theme)
+            .with_prompt(format!("Track {:02} title", track_num))
+            .with_initial_text(title.as_str())
+            .interact_text()?;
+        let new_artist: String = Input::with_theme(&console_theme)
+            .with_prompt(format!("Track {:02} artist", track_num))
+            .with_initial_text(artist.as_str())
+            .interact_text()?;
+        if new_title == title && new_artist == artist {
+            continue;
+        }
+
+        track.title = new_title;
+        if new_artist != artist {
+            // The edited artist replaces the whole credit. The first artist's
+            // ID is kept, as the closest match for MUSICBRAINZ_ARTISTID.
+            track.artist_credit.truncate(1);
+            if let Some(credit) = track.artist_credit.first_mut() {
+                credit.name = new_artist;
+                credit.joinphrase = None;
+            }
+        }
+        originals.push(MbOriginal {
+            track_num,
+            title,
+            artist,
+        });
+    }
+
+    Ok(originals)
+}
@@ -0,0 +1,437 @@
+use std::{
+    collections::BTreeMap,
+    ffi::CString,
+    fs::File,
+    io::{BufWriter, Read, Seek, SeekFrom, Write},
+    os::unix::ffi::OsStrExt,
+    path::{Path, PathBuf},
+    sync::mpsc::Receiver,
+};
+
+use anyhow::{anyhow, bail, Context};
+use cdparanoia::CD_FRAMEWORDS;
+use flac_bound::{FlacEncoder, FlacEncoderInitError, FlacEncoderState};
+use metaflac::block::PictureType;
+use tracing::{info, warn};
+
+use crate::{caa::CoverArt, checksum, interrupt, verify, CD_SAMPLE_RATE};
+
+#[derive(Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum OutputFormat {
+    /// Native FLAC (.flac)
+    Flac,
+    /// FLAC audio in an Ogg container (.ogg)
+    #[value(name = "oggflac")]
+    OggFlac,
+    /// Uncompressed PCM (.wav), tagged with a RIFF INFO chunk
+    Wav,
+}
+
+impl OutputFormat {
+    /// With `verify`, every file is decoded again after encoding and compared
+    /// against the ripped audio.
+    pub fn encoder(self, compression_level: u32, verify: bool) -> Box<dyn AudioEncoder> {
+        match self {
+            Self::Flac => Box::new(Flac {
+                ogg: false,
+                compression_level,
+                verify,
+            }),
+            Self::OggFlac => Box::new(Flac {
+                ogg: true,
+                compression_level,
+                verify,
+            }),
+            Self::Wav => Box::new(Wav { verify }),
+        }
+    }
+}
+
+pub trait AudioEncoder: Sync {
+    /// Extension of the encoded files, including the leading dot.
+    fn file_extension(&self) -> &str;
+
+    /// Rough size of the encoded audio relative to the raw PCM, used only to
+    /// give the user a ballpark figure.
+    fn estimated_compression_ratio(&self) -> f64;
+
+    /// Whether tags and pictures can be embedded in the encoded files.
+    fn supports_metadata(&self) -> bool;
+
+    /// Whether `path` looks like a fully-encoded file, rather than one left
+    /// behind by an interrupted rip.
+    fn is_complete(&self, path: &Path) -> bool;
+
+    fn encode(&self, job: &EncodeJob<'_>) -> anyhow::Result<()>;
+}
+
+/// A fully-read track waiting to be encoded.
+pub struct EncodeJob<'a> {
+    pub track_num: u32,
+    pub path: PathBuf,
+    pub channels: u32,
+    /// Interleaved 16-bit samples, exactly as read from the disc.
+    pub samples: Vec<i16>,
+    /// Vorbis comments to write to the encoded file.
+    pub tags: Vec<(String, String)>,
+    /// Cover art to embed in the encoded file, if any.
+    pub cover_art: Option<&'a CoverArt>,
+}
+
+impl EncodeJob<'_> {
+    fn mismatch(&self, sectors: (usize, usize)) -> verify::Mismatch {
+        verify::Mismatch {
+            track_num: self.track_num,
+            path: self.path.clone(),
+            sectors,
+        }
+    }
+}
+
+/// Encodes jobs until the sending side of the channel is dropped, and
+/// returns the tracks whose encoded audio failed verification.
+///
+/// Stops at any other failure, which drops the receiver so that the reader
+/// notices and stops sending work.
+pub fn encode_worker(
+    encoder: &dyn AudioEncoder,
+    jobs: Receiver<EncodeJob<'_>>,
+) -> anyhow::Result<Vec<u32>> {
+    let mut unverified_tracks = Vec::new();
+    for job in jobs {
+        match encoder.encode(&job) {
+            Err(error) if error.is::<verify::Mismatch>() => {
+                warn!("{}", error);
+                unverified_tracks.push(job.track_num);
+            }
+            result => result?,
+        }
+    }
+    Ok(unverified_tracks)
+}
+
+struct Flac {
+    ogg: bool,
+    compression_level: u32,
+    verify: bool,
+}
+
+impl AudioEncoder for Flac {
+    fn file_extension(&self) -> &str {
+        if self.ogg {
+            ".ogg"
+        } else {
+            ".flac"
+        }
+    }
+
+    fn estimated_compression_ratio(&self) -> f64 {
+        0.6
+    }
+
+    fn supports_metadata(&self) -> bool {
+        // metaflac can only edit native FLAC files.
+        !self.ogg
+    }
+
+    fn is_complete(&self, path: &Path) -> bool {
+        if self.ogg {
+            let mut magic = [0; 4];
+            return File::open(path)
+                .and_then(|mut file| file.read_exact(&mut magic))
+                .is_ok()
+                && &magic == b"OggS";
+        }
+        // libFLAC only fills in the sample count once encoding finishes.
+        metaflac::Tag::read_from_path(path)
+            .ok()
+            .and_then(|tag| tag.get_streaminfo().map(|info| info.total_samples > 0))
+            .unwrap_or(false)
+    }
+
+    fn encode(&self, job: &EncodeJob<'_>) -> anyhow::Result<()> {
+        let config = FlacEncoder::new()
+            .unwrap()
+            .channels(job.channels)
+            .sample_rate(CD_SAMPLE_RATE)
+            .bits_per_sample(16)
+            .compression_level(self.compression_level)
+            // Decode the output as it is written and compare it against the
+            // input, so that a bad encode fails instead of going unnoticed.
+            .verify(true);
+        let mut encoder = if self.ogg {
+            config.init_file_ogg(&job.path)
+        } else {
+            config.init_file(&job.path)
+        }
+        .map_err(|e| friendly_flac_init_error(e, &job.path))?;
+
+        let mut widen_buffer = [0i32; CD_FRAMEWORDS as usize];
+
+        let total_sectors = job.samples.len().div_ceil(CD_FRAMEWORDS as usize);
+        for (sector, sector_data) in job.samples.chunks(CD_FRAMEWORDS as usize).enumerate() {
+            if interrupt::interrupted() {
+                // The file is removed either way, so a failure to finish it
+                // cleanly does not matter.
+                let _ = encoder.finish();
+                std::fs::remove_file(&job.path)?;
+                bail!(
+                    "Interrupted after sector {} of {}; partial file removed ({:?})",
+                    sector,
+                    total_sectors,
+                    job.path
+                );
+            }
+            for (dst, src) in widen_buffer.iter_mut().zip(sector_data) {
+                *dst = (*src).into();
+            }
+            encoder
+                .process_interleaved(
+                    &widen_buffer[..sector_data.len()],
+                    sector_data.len() as u32 / job.channels,
+                )
+                .map_err(|()| friendly_flac_error(encoder.state(), &job.path))?;
+        }
+
+        encoder
+            .finish()
+            .map_err(|enc| friendly_flac_error(enc.state(), &job.path))?;
+
+        if self.verify {
+            let decoded = verify::decode_flac(&job.path, self.ogg)?;
+            if let Some(sectors) = verify::mismatched_sectors(&job.samples, &decoded) {
+                return Err(job.mismatch(sectors).into());
+            }
+        }
+
+        if !self.supports_metadata() {
+            return Ok(());
+        }
+
+        let md5 = checksum::read_flac_md5(&job.path)?;
+        info!("{:?}: MD5 {}", job.path, checksum::md5_hex(&md5));
+
+        let mut tag = metaflac::Tag::read_from_path(&job.path)?;
+        // Keys such as MUSICBRAINZ_ARTISTID can have several values.
+        let mut values: BTreeMap<&str, Vec<&str>> = BTreeMap::new();
+        for (key, value) in &job.tags {
+            values.entry(key.as_str()).or_default().push(value.as_str());
+        }
+        for (key, values) in values {
+            tag.set_vorbis(key, values);
+        }
+        if let Some(cover_art) = job.cover_art {
+            tag.add_picture(
+                cover_art.mime_type.clone(),
+                PictureType::CoverFront,
+                cover_art.data.clone(),
+            );
+        }
+        tag.save()?;
+
+        Ok(())
+    }
+}
+
+/// RIFF INFO chunk IDs for the Vorbis comments that have one.
+const WAV_INFO_TAGS: &[(&str, &[u8; 4])] = &[
+    ("TITLE", b"INAM"),
+    ("ARTIST", b"IART"),
+    ("ALBUM", b"IPRD"),
+    ("DATE", b"ICRD"),
+    ("GENRE", b"IGNR"),
+    ("TRACKNUMBER", b"ITRK"),
+    ("COMMENT", b"ICMT"),
+];
+
+/// Length of the RIFF, "fmt " and "data" chunk headers that precede the
+/// audio in the files written by `Wav`.
+const WAV_HEADER_LEN: u64 = 44;
+
+struct Wav {
+    verify: bool,
+}
+
+impl AudioEncoder for Wav {
+    fn file_extension(&self) -> &str {
+        ".wav"
+    }
+
+    fn estimated_compression_ratio(&self) -> f64 {
+        1.0
+    }
+
+    fn supports_metadata(&self) -> bool {
+        // Only the few tags in WAV_INFO_TAGS, and no pictures.
+        false
+    }
+
+    fn is_complete(&self, path: &Path) -> bool {
+        // The RIFF size is only filled in once the whole file is written.
+        let mut header = [0; 8];
+        let Ok(mut file) = File::open(path) else {
+            return false;
+        };
+        let Ok(metadata) = file.metadata() else {
+            return false;
+        };
+        file.read_exact(&mut header).is_ok()
+            && &header[..4] == b"RIFF"
+            && u32::from_le_bytes(header[4..].try_into().unwrap()) as u64 + 8 == metadata.len()
+    }
+
+    fn encode(&self, job: &EncodeJob<'_>) -> anyhow::Result<()> {
+        let file =
+            File::create(&job.path).with_context(|| format!("failed to create {:?}", job.path))?;
+        let mut out = BufWriter::new(file);
+        let data_len = job.samples.len() as u32 * 2;
+        let block_align = job.channels * 2;
+
+        // The RIFF size is left as 0 until the file is complete.
+        out.write_all(b"RIFF")?;
+        out.write_all(&0u32.to_le_bytes())?;
+        out.write_all(b"WAVE")?;
+        out.write_all(b"fmt ")?;
+        out.write_all(&16u32.to_le_bytes())?;
+        out.write_all(&1u16.to_le_bytes())?; // PCM
+        out.write_all(&(job.channels as u16).to_le_bytes())?;
+        out.write_all(&CD_SAMPLE_RATE.to_le_bytes())?;
+        out.write_all(&(CD_SAMPLE_RATE * block_align).to_le_bytes())?;
+        out.write_all(&(block_align as u16).to_le_bytes())?;
+        out.write_all(&16u16.to_le_bytes())?;
+        out.write_all(b"data")?;
+        out.write_all(&data_len.to_le_bytes())?;
+
+        let total_sectors = job.samples.len().div_ceil(CD_FRAMEWORDS as usize);
+        for (sector, sector_data) in job.samples.chunks(CD_FRAMEWORDS as usize).enumerate() {
+            if interrupt::interrupted() {
+                drop(out);
+                std::fs::remove_file(&job.path)?;
+                bail!(
+                    "Interrupted after sector {} of {}; partial file removed ({:?})",
+                    sector,
+                    total_sectors,
+                    job.path
+                );
+            }
+            for sample in sector_data {
+                out.write_all(&sample.to_le_bytes())?;
+            }
+        }
+
+        let info = wav_info_chunk(&job.tags);
+        out.write_all(&info)?;
+        let riff_len = WAV_HEADER_LEN as u32 - 8 + data_len + info.len() as u32;
+        out.seek(SeekFrom::Start(4))?;
+        out.write_all(&riff_len.to_le_bytes())?;
+        out.flush()
+            .with_context(|| format!("failed to write {:?}", job.path))?;
+
+        if self.verify {
+            let mut file = File::open(&job.path)?;
+            file.seek(SeekFrom::Start(WAV_HEADER_LEN))?;
+            let mut data = vec![0; data_len as usize];
+            file.read_exact(&mut data)?;
+            let written: Vec<i16> = data
+                .chunks_exact(2)
+                .map(|bytes| i16::from_le_bytes([bytes[0], bytes[1]]))
+                .collect();
+            if let Some(sectors) = verify::mismatched_sectors(&job.samples, &written) {
+                return Err(job.mismatch(sectors).into());
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Builds a LIST chunk of RIFF INFO tags from the Vorbis comments that have
+/// an equivalent, or nothing if none do. Only the first value of each is
+/// kept.
+fn wav_info_chunk(tags: &[(String, String)]) -> Vec<u8> {
+    let mut info = Vec::new();
+    for (key, id) in WAV_INFO_TAGS {
+        let Some((_, value)) = tags.iter().find(|(tag, _)| tag == key) else {
+            continue;
+        };
+        // Strings are NUL-terminated, and chunks are padded to even lengths.
+        let len = value.len() + 1;
+        info.extend_from_slice(*id);
+        info.extend_from_slice(&(len as u32).to_le_bytes());
+        info.extend_from_slice(value.as_bytes());
+        info.push(0);
+        if len % 2 == 1 {
+            info.push(0);
+        }
+    }
+    if info.is_empty() {
+        return info;
+    }
+
+    let mut chunk = Vec::with_capacity(info.len() + 12);
+    chunk.extend_from_slice(b"LIST");
+    chunk.extend_from_slice(&(info.len() as u32 + 4).to_le_bytes());
+    chunk.extend_from_slice(b"INFO");
+    chunk.extend_from_slice(&info);
+    chunk
+}
+
+fn friendly_flac_init_error(error: FlacEncoderInitError, output_path: &Path) -> anyhow::Error {
+    let message = match error {
+        FlacEncoderInitError::EncoderError => "Failed to set up the encoder",
+        FlacEncoderInitError::UnsupportedContainer => {
+            "libFLAC was built without support for this container format"
+        }
+        FlacEncoderInitError::InvalidCallbacks => "Encoder was not properly initialized",
+        FlacEncoderInitError::InvalidNumberOfChannels
+        | FlacEncoderInitError::InvalidBitsPerSample
+        | FlacEncoderInitError::InvalidSampleRate => "Track has an unsupported audio format",
+        _ => {
+            return anyhow!(
+                "Failed to initialize encoder for {:?}: {:?}",
+                output_path,
+                error
+            )
+        }
+    };
+    anyhow!("{} ({:?})", message, output_path)
+}
+
+fn friendly_flac_error(state: FlacEncoderState, output_path: &Path) -> anyhow::Error {
+    let message = match state {
+        FlacEncoderState::OggError => "OGG stream error during encoding",
+        FlacEncoderState::Uninitialized => "Encoder was not properly initialized",
+        FlacEncoderState::VerifyDecoderError | FlacEncoderState::VerifyMismatchInAudioData => {
+            "Encoded audio failed verification"
+        }
+        FlacEncoderState::MemoryAllocationError => "Ran out of memory while encoding",
+        FlacEncoderState::ClientError
+        | FlacEncoderState::IoError
+        | FlacEncoderState::FramingError => {
+            return match free_space(output_path) {
+                Some(free) => anyhow!(
+                    "Failed to write to output file {:?} (disk full? {:.1} MB free)",
+                    output_path,
+                    free as f64 / 1_000_000.0
+                ),
+                None => anyhow!(
+                    "Failed to write to output file {:?} (disk full?)",
+                    output_path
+                ),
+            };
+        }
+        FlacEncoderState::Ok => "Encoder failed without reporting an error",
+    };
+    anyhow!("{} ({:?})", message, output_path)
+}
+
+/// Bytes available to unprivileged users on the filesystem containing `path`.
+fn free_space(path: &Path) -> Option<u64> {
+    let dir = path.parent()?;
+    let c_dir = CString::new(dir.as_os_str().as_bytes()).ok()?;
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statvfs(c_dir.as_ptr(), &mut stat) } != 0 {
+        return None;
+    }
+    Some(stat.f_bavail as u64 * stat.f_frsize as u64)
+}
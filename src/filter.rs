@@ -0,0 +1,153 @@
+//! Client-side filtering of the releases that match a disc, using a small
+//! subset of the MusicBrainz search syntax:
+//!
+//! ```text
+//! country:JP AND status:Official
+//! country=GB OR country=XE
+//! date:1999 AND NOT disambiguation:"club edition"
+//! ```
+//!
+//! `AND` binds tighter than `OR`. Values are compared case-insensitively;
+//! dates match by prefix, so `date:1999` matches any date in 1999.
+
+use anyhow::{bail, Context};
+
+use crate::mb::Release;
+
+#[derive(Clone, Debug)]
+pub struct ReleaseFilter {
+    /// Alternatives joined by `OR`, each a list of conditions joined by `AND`.
+    alternatives: Vec<Vec<Condition>>,
+}
+
+#[derive(Clone, Debug)]
+struct Condition {
+    field: Field,
+    value: String,
+    negate: bool,
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Field {
+    Country,
+    Date,
+    Status,
+    Barcode,
+    Disambiguation,
+}
+
+impl Field {
+    fn from_name(name: &str) -> anyhow::Result<Self> {
+        Ok(match name.to_ascii_lowercase().as_str() {
+            "country" => Self::Country,
+            "date" => Self::Date,
+            "status" => Self::Status,
+            "barcode" => Self::Barcode,
+            "disambiguation" => Self::Disambiguation,
+            _ => bail!(
+                "unknown field {:?} (expected country, date, status, barcode or disambiguation)",
+                name
+            ),
+        })
+    }
+
+    fn value<'a>(&self, release: &'a Release) -> Option<&'a str> {
+        match self {
+            Self::Country => release.country.as_deref(),
+            Self::Date => Some(release.date.as_str()),
+            Self::Status => release.status.as_deref(),
+            Self::Barcode => release.barcode.as_deref(),
+            Self::Disambiguation => Some(release.disambiguation.as_str()),
+        }
+    }
+}
+
+impl Condition {
+    fn matches(&self, release: &Release) -> bool {
+        let value = self.field.value(release).unwrap_or("").to_lowercase();
+        let expected = self.value.to_lowercase();
+        let matched = match self.field {
+            Field::Date => value.starts_with(&expected),
+            _ => value == expected,
+        };
+        matched != self.negate
+    }
+}
+
+impl ReleaseFilter {
+    pub fn parse(query: &str) -> anyhow::Result<Self> {
+        let mut alternatives = vec![Vec::new()];
+        let mut negate = false;
+        let mut expect_condition = true;
+
+        for token in tokenize(query)? {
+            match token.as_str() {
+                "AND" | "OR" if expect_condition => {
+                    bail!("expected a condition before {}", token)
+                }
+                "AND" => expect_condition = true,
+                "OR" => {
+                    alternatives.push(Vec::new());
+                    expect_condition = true;
+                }
+                "NOT" => negate = !negate,
+                _ => {
+                    if !expect_condition {
+                        bail!("expected AND or OR before {:?}", token);
+                    }
+                    let (field, value) = token
+                        .split_once([':', '='])
+                        .with_context(|| format!("expected field:value, got {:?}", token))?;
+                    alternatives.last_mut().unwrap().push(Condition {
+                        field: Field::from_name(field)?,
+                        value: value.to_string(),
+                        negate,
+                    });
+                    negate = false;
+                    expect_condition = false;
+                }
+            }
+        }
+        if expect_condition {
+            bail!("incomplete filter: {:?}", query);
+        }
+
+        Ok(Self { alternatives })
+    }
+
+    pub fn matches(&self, release: &Release) -> bool {
+        self.alternatives.iter().any(|conditions| {
+            conditions
+                .iter()
+                .all(|condition| condition.matches(release))
+        })
+    }
+}
+
+/// Splits a query on whitespace, keeping double-quoted text together and
+/// removing the quotes.
+fn tokenize(query: &str) -> anyhow::Result<Vec<String>> {
+    let mut tokens = Vec::new();
+    let mut token = String::new();
+    let mut in_quotes = false;
+
+    for c in query.chars() {
+        match c {
+            '"' => in_quotes = !in_quotes,
+            c if c.is_whitespace() && !in_quotes => {
+                if !token.is_empty() {
+                    tokens.push(std::mem::take(&mut token));
+                }
+            }
+            c => token.push(c),
+        }
+    }
+    if in_quotes {
+        bail!("unterminated quote in filter: {:?}", query);
+    }
+    if !token.is_empty() {
+        tokens.push(token);
+    }
+
+    Ok(tokens)
+}
@@ -0,0 +1,18 @@
+//! Ctrl+C handling, so that a rip can stop between sectors and clean up after
+//! itself instead of leaving a truncated file behind.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use anyhow::Context;
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+pub fn install_handler() -> anyhow::Result<()> {
+    ctrlc::set_handler(|| INTERRUPTED.store(true, Ordering::Relaxed))
+        .context("failed to install Ctrl+C handler")
+}
+
+/// Whether the user has pressed Ctrl+C.
+pub fn interrupted() -> bool {
+    INTERRUPTED.load(Ordering::Relaxed)
+}
@@ -0,0 +1,43 @@
+use crate::mb;
+
+/// Prints every disc ID attached to a release, marking the one that matches
+/// `my_disc_id` if given.
+pub fn run(client: &mb::Client, release_id: &str, my_disc_id: Option<&str>) -> anyhow::Result<()> {
+    let release = mb::Release::lookup(client, release_id)?;
+
+    println!("{} - {}", release.artist_string(), release.title);
+    println!();
+    println!(
+        "  {:<28}  {:>6}  {:>6}  Offsets",
+        "Disc ID", "Medium", "Tracks"
+    );
+
+    let mut found = false;
+    for medium in &release.media {
+        for disc in &medium.discs {
+            let is_mine = my_disc_id == Some(disc.id.as_str());
+            found |= is_mine;
+
+            let offsets: Vec<String> = disc.offsets.iter().map(u32::to_string).collect();
+            println!(
+                "{} {:<28}  {:>6}  {:>6}  {}",
+                if is_mine { "*" } else { " " },
+                disc.id,
+                medium.position,
+                medium.track_count,
+                offsets.join(" "),
+            );
+        }
+    }
+
+    if let Some(my_disc_id) = my_disc_id {
+        println!();
+        if found {
+            println!("Disc {} matches the entry marked with *", my_disc_id);
+        } else {
+            println!("Disc {} not found in this release", my_disc_id);
+        }
+    }
+
+    Ok(())
+}
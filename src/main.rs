@@ -1,95 +1,1109 @@
+mod accuraterip;
+mod batch;
+mod caa;
+mod cddb;
+mod checksum;
+mod config;
+mod crc;
+mod cue;
+mod deemphasis;
+mod discid;
+mod drive;
+mod edit;
+mod encode;
+mod filter;
+mod interrupt;
+mod list_discs;
 pub mod mb;
+mod nfo;
+mod offset;
+mod paranoia;
+mod playlist;
+mod preview;
+mod replaygain;
+mod report;
+mod silence;
+mod source;
+mod speed_test;
+mod status;
+mod tags;
+mod template;
+mod util;
+mod verify;
 
 use std::{
-    collections::HashSet,
+    borrow::Cow,
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
     ffi::{c_int, c_long, CString},
-    io::SeekFrom,
-    path::PathBuf,
-    time::Instant,
+    path::{Path, PathBuf},
+    sync::mpsc,
+    thread,
+    time::{Duration, Instant},
 };
 
+use ::discid::DiscId;
 use aho_corasick::AhoCorasick;
-use anyhow::{anyhow, bail, Context};
-use cdparanoia::{CdromDrive, CdromParanoia, ParanoiaMode, CD_FRAMEWORDS};
-use clap::Parser;
-use dialoguer::{theme::ColorfulTheme, Confirm, Select};
-use discid::DiscId;
-use flac_bound::FlacEncoder;
+use anyhow::{bail, Context};
+use cdparanoia::{CdromDrive, CdromParanoia, CD_FRAMEWORDS};
+use clap::{CommandFactory, FromArgMatches};
+use dialoguer::{theme::ColorfulTheme, Confirm, Input, Select};
+use indicatif::{ProgressBar, ProgressStyle};
+use tracing::{debug, info, trace, warn};
+use tracing_subscriber::EnvFilter;
+use unicode_normalization::UnicodeNormalization;
 
-const CD_SAMPLE_RATE: u32 = 44100;
+pub const CD_SAMPLE_RATE: u32 = 44100;
+
+/// Sectors of audio played per second at 1x speed.
+pub const CD_SECTORS_PER_SECOND: u32 = 75;
 
 #[derive(clap::Parser)]
+#[command(subcommand_negates_reqs = true)]
 pub struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// Print debugging output, including cdparanoia's messages. A shortcut
+    /// for RUST_LOG=ripoff=debug; RUST_LOG takes precedence if set.
+    #[arg(short, long)]
+    verbose: bool,
+
+    /// Base URL of the MusicBrainz web service, for using a mirror.
+    #[arg(long, default_value = mb::DEFAULT_ROOT_URL)]
+    mb_root_url: String,
+
+    /// How long to wait for a response from MusicBrainz before giving up on
+    /// the request, in seconds.
+    #[arg(long, default_value_t = mb::DEFAULT_TIMEOUT.as_secs())]
+    mb_timeout_secs: u64,
+
+    /// Number of times to retry a MusicBrainz request that failed to get a
+    /// response. Requests refused because the server is busy are retried
+    /// separately, for up to about five minutes.
+    #[arg(long, default_value_t = mb::DEFAULT_MAX_RETRIES)]
+    mb_retries: u32,
+
+    /// Directory to cache MusicBrainz disc ID lookups in. A disc that was
+    /// looked up before is read from the cache instead of the network, until
+    /// the cached lookup is older than `--mb-cache-ttl-days`.
+    #[arg(long)]
+    mb_cache_dir: Option<PathBuf>,
+
+    /// Look the disc up on MusicBrainz even if it is in the cache, and
+    /// update the cache.
+    #[arg(long, requires = "mb_cache_dir")]
+    refresh_cache: bool,
+
+    /// Number of days after which a cached lookup is refreshed from
+    /// MusicBrainz.
+    #[arg(long, default_value_t = mb::DEFAULT_CACHE_TTL.as_secs() / (24 * 60 * 60))]
+    mb_cache_ttl_days: u64,
+
+    /// Use a cached lookup however old it is, without refreshing it from
+    /// MusicBrainz. For offline use with a cache that was filled earlier.
+    #[arg(long, requires = "mb_cache_dir", conflicts_with = "refresh_cache")]
+    skip_mb_if_cached: bool,
+
+    /// Config file to read option defaults from. Options given on the
+    /// command line take precedence. (default:
+    /// $XDG_CONFIG_HOME/ripoff/config.toml on Linux)
+    #[arg(long)]
+    config: Option<PathBuf>,
+
     /// Path to CD-ROM device. (default: /dev/cdrom)
     #[arg(short, long)]
     disc_device: Option<String>,
 
     /// Base path for output files.
-    output_path: PathBuf,
+    #[arg(required_unless_present_any = ["device_speed_test", "list_devices"])]
+    output_path: Option<PathBuf>,
 
+    /// Make file names usable on NTFS and Windows, by replacing the
+    /// characters they forbid and avoiding reserved device names.
     #[arg(short, long)]
     ntfs_filenames: bool,
+
+    /// Keep file names in the Unicode normalization form MusicBrainz returns
+    /// them in, instead of normalizing them to NFC.
+    #[arg(long)]
+    no_unicode_normalize: bool,
+
+    /// Place the album directory inside a per-artist subdirectory.
+    #[arg(long)]
+    artist_subdir: bool,
+
+    /// Name the artist subdirectory by the artist's sort name (e.g.
+    /// "Beatles, The") instead of their display name.
+    #[arg(long, requires = "artist_subdir")]
+    artist_subdir_sort: bool,
+
+    /// Name directories by the artist's sort name (e.g. "Beatles, The")
+    /// instead of their display name, so that they sort alphabetically. Tags
+    /// keep the display name. This covers the artist subdirectory too, so it
+    /// cannot be combined with `--artist-subdir-sort`, which only renames the
+    /// subdirectory.
+    #[arg(long, conflicts_with = "artist_subdir_sort")]
+    sort_artist: bool,
+
+    /// FLAC compression level, from 0 to 8. Lower levels encode much faster
+    /// for slightly larger files; higher levels squeeze out a few more percent
+    /// at a large cost in CPU time. The audio is lossless at every level.
+    #[arg(long, default_value_t = 5, value_parser = clap::value_parser!(u32).range(0..=8))]
+    compression_level: u32,
+
+    /// Decode each file after encoding it and compare it against the ripped
+    /// audio. Catches corruption on the way to the disk, but roughly doubles
+    /// the time spent encoding.
+    #[arg(long)]
+    verify: bool,
+
+    /// Measure the loudness of each track and of the album once all tracks
+    /// are ripped, and tag them with ReplayGain 2.0 gains and peaks. The
+    /// album gain only covers the tracks ripped in this run.
+    #[arg(long)]
+    replaygain: bool,
+
+    /// How hard cdparanoia works to get an accurate read, as one mode or
+    /// several joined with `+` (e.g. `overlap+verify`):
+    ///
+    /// - full: every check below. Slowest, most accurate.
+    /// - verify: compare overlapping reads to catch jitter and dropped bytes.
+    /// - fragment: also verify within reads, not just at their edges.
+    /// - overlap: re-read overlapping sectors to correct jitter.
+    /// - scratch: detect and repair scratches.
+    /// - repair: patch damaged samples that cannot be read correctly.
+    /// - neverskip: retry bad sectors forever instead of giving up.
+    /// - disable: plain reads with no checks. Fastest; fine for clean discs
+    ///   in a drive that reads accurately.
+    ///
+    /// Per-track modes in the config file take precedence.
+    #[arg(long, default_value = "full", value_parser = paranoia::validate_paranoia_mode)]
+    paranoia_mode: String,
+
+    /// Read offset of the drive, in samples. A positive offset means the
+    /// drive reads ahead of where it was asked to, a negative one that it
+    /// reads behind. The correct value for most drives is listed in the
+    /// AccurateRip drive database. Audio beyond the edges of the disc is
+    /// replaced with silence.
+    #[arg(long, default_value_t = 0, allow_hyphen_values = true)]
+    read_offset: i32,
+
+    /// Limit the drive's read speed to this multiple of playback speed
+    /// (e.g. 4, 8, 16). Slower reads are quieter and can be more accurate.
+    /// Drives may round the speed or ignore the request entirely; the speed
+    /// actually achieved is shown after each track.
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    max_speed: Option<u32>,
+
+    /// Eject the disc once it has been ripped and all files are written.
+    /// The disc is kept in the drive if the rip fails or is interrupted.
+    #[arg(long, overrides_with = "no_eject")]
+    eject: bool,
+
+    /// Keep the disc in the drive after ripping, even if `eject` is set in
+    /// the config file.
+    #[arg(long, overrides_with = "eject")]
+    no_eject: bool,
+
+    /// Name the track files with a template such as "{track:02} {title}".
+    /// Fields are track, disc, title, artist, album, year, version, country
+    /// and catalog; a number after a colon zero-pads a field. The file
+    /// extension is added automatically. (default: "{track:02} {title}", or
+    /// "{disc:02}-{track:02} {title}" for multi-disc releases)
+    #[arg(long, value_parser = template::Template::parse)]
+    filename_template: Option<template::Template>,
+
+    /// Name the album directory with a template such as "{artist} - {year} -
+    /// {album} ({country})", using the fields artist, album, year, country
+    /// and catalog. Brackets and separators around fields without a value
+    /// are dropped. (default: "{artist} - {album}")
+    #[arg(long, value_parser = template::Template::parse_dirname)]
+    dirname_template: Option<template::Template>,
+
+    /// Text between the track number and the title in file names, such as
+    /// " - " for "01 - Title.flac". Not used with --filename-template.
+    #[arg(long, default_value = " ", conflicts_with = "filename_template")]
+    track_separator: String,
+
+    /// Text between the artist and the album title in the album directory
+    /// name. Not used with --dirname-template.
+    #[arg(long, default_value = " - ", conflicts_with = "dirname_template")]
+    dir_separator: String,
+
+    /// Number of digits to zero-pad track numbers, and disc numbers of
+    /// multi-disc releases, to in file names. Not used with
+    /// --filename-template.
+    #[arg(
+        long,
+        default_value_t = 2,
+        value_parser = clap::value_parser!(u8).range(1..=4),
+        conflicts_with = "filename_template"
+    )]
+    track_pad_width: u8,
+
+    /// Audio format of the output files.
+    #[arg(long, value_enum, default_value_t = encode::OutputFormat::Flac)]
+    format: encode::OutputFormat,
+
+    /// When to remove pre-emphasis from tracks. With "auto", only tracks
+    /// flagged as pre-emphasized in the TOC are filtered.
+    #[arg(long, value_enum, default_value_t = Deemphasis::Auto)]
+    deemphasis: Deemphasis,
+
+    /// Don't remove pre-emphasis from any track, the same as
+    /// `--deemphasis never`. Pre-emphasized tracks are still tagged.
+    #[arg(long, conflicts_with = "deemphasis")]
+    no_deemphasis: bool,
+
+    /// Whether to embed the front cover art in each track, save it as a file
+    /// in the album directory, or both.
+    #[arg(long, value_enum, default_value_t = CoverArtOutput::Both)]
+    cover_art_output: CoverArtOutput,
+
+    /// Don't fetch any cover art. Same as `--cover-art-output none`.
+    #[arg(long, conflicts_with_all = ["cover_art_output", "cover_art_type"])]
+    no_cover_art: bool,
+
+    /// File name for saved cover art. (default: cover.jpg or cover.png)
+    #[arg(long)]
+    cover_art_filename: Option<String>,
+
+    /// Which cover art images to save. With "all", every image in the Cover
+    /// Art Archive (back, booklet, medium, ...) is saved alongside the
+    /// tracks; only the front cover is ever embedded.
+    #[arg(long, value_enum, default_value_t = CoverArtType::Front)]
+    cover_art_type: CoverArtType,
+
+    /// Save the images from `--cover-art-type all` in a subdirectory of the
+    /// album directory. (default name: AlbumArt)
+    #[arg(long, num_args = 0..=1, default_missing_value = "AlbumArt")]
+    albumart_dir: Option<String>,
+
+    /// Name the images from `--cover-art-type all` by their Cover Art Archive
+    /// ID instead of their type.
+    #[arg(long)]
+    cover_art_name_by_index: bool,
+
+    /// Measure the drive's read speed instead of ripping.
+    #[arg(long)]
+    device_speed_test: bool,
+
+    /// List the CD drives in /dev with their model and firmware, and exit.
+    #[arg(long)]
+    list_devices: bool,
+
+    /// Number of sectors to read during the speed test. (default: one
+    /// minute of audio)
+    #[arg(long, requires = "device_speed_test", default_value_t = 75 * 60)]
+    sectors: u32,
+
+    /// Show the MusicBrainz disc ID submission URL prominently, and copy it
+    /// to the clipboard when built with the "clipboard" feature.
+    #[arg(long)]
+    show_submission_url: bool,
+
+    /// Look up the release and print the files that would be created, without
+    /// opening the drive or ripping anything.
+    #[arg(long)]
+    dry_run: bool,
+
+    /// Rip the audio hidden in the pre-gap before track 1, if there is any,
+    /// into "00 Hidden Track".
+    #[arg(long)]
+    rip_htoa: bool,
+
+    /// Edit the title and artist of each track before ripping. The edited
+    /// values are used for file names and tags; the NFO lists the originals.
+    #[arg(long, conflicts_with = "json")]
+    edit_metadata: bool,
+
+    /// Rip without asking any questions, and print a JSON summary of the rip
+    /// to stdout when done. Everything else is printed to stderr instead.
+    /// Fails if more than one release matches the disc.
+    #[arg(long)]
+    json: bool,
+
+    /// Print the planned output directory structure before ripping.
+    #[arg(long)]
+    output_tree_info: bool,
+
+    /// Print a CDDB (freedb) xmcd entry for the disc, generated from the
+    /// MusicBrainz data.
+    #[arg(long)]
+    print_cddb: bool,
+
+    /// Only re-rip the tracks marked as failed in the album directory's
+    /// status file by a previous run.
+    #[arg(long)]
+    retry_failed: bool,
+
+    /// Only rip these tracks, given as a comma-separated list of track
+    /// numbers and ranges such as "1,3,5-8".
+    #[arg(long, value_parser = parse_track_list)]
+    tracks: Option<BTreeSet<u32>>,
+
+    /// Continue an interrupted rip into an existing album directory, skipping
+    /// the tracks that were already encoded completely.
+    #[arg(long, conflicts_with_all = ["retry_failed", "split_at_silence"])]
+    resume: bool,
+
+    /// Split the disc into tracks at stretches of silence instead of at the
+    /// TOC track boundaries, for live recordings mastered as a single track.
+    #[arg(long, conflicts_with = "retry_failed")]
+    split_at_silence: bool,
+
+    /// Shortest stretch of silence that starts a new track with
+    /// `--split-at-silence`, in milliseconds.
+    #[arg(long, requires = "split_at_silence", default_value_t = 2000)]
+    silence_min_duration: u32,
+
+    /// Read the disc ID and track layout from a TOC file instead of from the
+    /// drive. The file holds one line of the form
+    /// `disc_id,first_track,last_track,leadout,offset1,offset2,...`.
+    #[arg(long)]
+    disc_toc_file: Option<PathBuf>,
+
+    /// Read the disc's audio from a raw PCM dump (signed 16-bit little-endian
+    /// stereo at 44.1 kHz) instead of from the drive. The tracks are cut at
+    /// the offsets of the matching MusicBrainz disc.
+    #[arg(long)]
+    raw_pcm_input: Option<PathBuf>,
+
+    /// Look up this disc ID on MusicBrainz instead of reading one from the
+    /// drive, and print the files and tags a rip of it would produce. The
+    /// drive is never opened, so this works on machines without one.
+    #[arg(long, conflicts_with_all = ["disc_toc_file", "raw_pcm_input"])]
+    test_disc_id: Option<String>,
+
+    /// Compare the CRC32 of each ripped track against a reference file from
+    /// another ripper, with one `track_num,crc32_hex` line per track.
+    #[arg(long)]
+    compare_crc_file: Option<PathBuf>,
+
+    /// Only consider releases matching a query such as
+    /// "country:JP AND status:Official". Supported fields are country, date,
+    /// status, barcode and disambiguation.
+    #[arg(long, value_parser = filter::ReleaseFilter::parse)]
+    mb_release_filter: Option<filter::ReleaseFilter>,
+
+    /// Only consider releases with this status. If none of the releases
+    /// matching the disc have it, all of them are shown instead.
+    #[arg(long, value_enum)]
+    mb_status: Option<ReleaseStatus>,
+
+    /// Only consider releases whose release group has this primary type. If
+    /// none of the releases matching the disc have it, all of them are shown
+    /// instead.
+    #[arg(long, value_enum)]
+    mb_type: Option<ReleaseType>,
+
+    /// Request more data in the MusicBrainz disc ID lookup, on top of what
+    /// ripoff always includes (e.g. url-rels, genres or annotation). Can be
+    /// given more than once.
+    #[arg(
+        long = "mb-include",
+        value_name = "INCLUDE",
+        value_parser = clap::builder::PossibleValuesParser::new(
+            mb::DEFAULT_DISC_INCLUDES.iter().chain(mb::EXTRA_DISC_INCLUDES).copied()
+        )
+    )]
+    mb_includes: Vec<String>,
+
+    /// Number of genres to tag each track with, taken from the most popular
+    /// MusicBrainz tags of the recording or else of the release.
+    #[arg(long, default_value_t = 1)]
+    genre_count: usize,
+
+    /// Don't write an M3U playlist of the ripped tracks.
+    #[arg(long)]
+    no_playlist: bool,
+
+    /// Write a single playlist for all discs of a multi-disc release instead
+    /// of one per disc.
+    #[arg(long, conflicts_with = "no_playlist")]
+    combined_playlist: bool,
+
+    /// Refer to the tracks in M3U playlists by paths relative to the
+    /// playlist, so that the album directory can be moved. This is the
+    /// default.
+    #[arg(long, overrides_with = "m3u_absolute")]
+    m3u_relative: bool,
+
+    /// Refer to the tracks in M3U playlists by absolute paths.
+    #[arg(long, overrides_with = "m3u_relative")]
+    m3u_absolute: bool,
+
+    /// Format of the checksum manifest written to the album directory for the
+    /// tracks ripped in this run. The files are hashed as they are on disk.
+    #[arg(
+        long,
+        alias = "checksum-algorithm",
+        value_enum,
+        default_value_t = checksum::ManifestFormat::Sha256
+    )]
+    checksum_format: checksum::ManifestFormat,
+
+    /// Don't write a checksum manifest.
+    #[arg(long, conflicts_with = "checksum_format")]
+    no_checksums: bool,
+
+    /// Free-text note about the physical disc, stored in the COMMENT tag of
+    /// every track and in the NFO.
+    #[arg(long, env = "RIPOFF_DISC_NOTES")]
+    disc_notes: Option<String>,
+}
+
+#[derive(clap::Subcommand)]
+enum Command {
+    /// List the disc IDs attached to a release in MusicBrainz.
+    ListDiscs {
+        /// MusicBrainz ID of the release.
+        #[arg(long)]
+        release_id: String,
+
+        /// Also read the disc in the drive and show which entry matches it.
+        #[arg(long)]
+        check_my_disc: bool,
+    },
+    /// Rip several discs unattended, as described in a TOML batch file.
+    Batch {
+        /// Path to the batch file.
+        #[arg(long)]
+        file: PathBuf,
+    },
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum Deemphasis {
+    Auto,
+    Always,
+    Never,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum ReleaseStatus {
+    Official,
+    Promotion,
+    Bootleg,
+    PseudoRelease,
+}
+
+impl ReleaseStatus {
+    /// The status as MusicBrainz names it.
+    fn mb_name(self) -> &'static str {
+        match self {
+            Self::Official => "Official",
+            Self::Promotion => "Promotion",
+            Self::Bootleg => "Bootleg",
+            Self::PseudoRelease => "Pseudo-Release",
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum ReleaseType {
+    Album,
+    Single,
+    Ep,
+    Broadcast,
+    Other,
+}
+
+impl ReleaseType {
+    /// The primary type as MusicBrainz names it.
+    fn mb_name(self) -> &'static str {
+        match self {
+            Self::Album => "Album",
+            Self::Single => "Single",
+            Self::Ep => "EP",
+            Self::Broadcast => "Broadcast",
+            Self::Other => "Other",
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum CoverArtType {
+    Front,
+    All,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum CoverArtOutput {
+    Embedded,
+    File,
+    Both,
+    None,
+}
+
+impl CoverArtOutput {
+    fn embedded(self) -> bool {
+        matches!(self, Self::Embedded | Self::Both)
+    }
+
+    fn file(self) -> bool {
+        matches!(self, Self::File | Self::Both)
+    }
 }
 
-enum PathSanitizer {
-    Default,
-    Ntfs(AhoCorasick),
+struct PathSanitizer {
+    /// Replaces the characters NTFS forbids, if set.
+    ntfs: Option<AhoCorasick>,
+    /// Whether names are normalized to NFC first, so that the same name
+    /// always maps to the same bytes whatever form MusicBrainz returned.
+    normalize_unicode: bool,
 }
 
 impl PathSanitizer {
     pub fn default() -> Self {
-        Self::Default
+        Self {
+            ntfs: None,
+            normalize_unicode: true,
+        }
     }
 
     pub fn ntfs() -> Self {
-        Self::Ntfs(AhoCorasick::new(["/", ":", "?", "\"", "|", "*"]).unwrap())
+        Self {
+            ntfs: Some(AhoCorasick::new(["/", ":", "?", "\"", "|", "*"]).unwrap()),
+            normalize_unicode: true,
+        }
+    }
+
+    /// Keeps names in whatever Unicode normalization form they arrive in.
+    pub fn without_unicode_normalization(self) -> Self {
+        Self {
+            normalize_unicode: false,
+            ..self
+        }
+    }
+
+    /// Shortens the stem of `filename` so that the whole name, including its
+    /// extension, fits in `max_bytes` bytes of UTF-8.
+    pub fn truncate(filename: &str, max_bytes: usize) -> String {
+        if filename.len() <= max_bytes {
+            return filename.to_string();
+        }
+        let (stem, extension) = match filename.rfind('.') {
+            Some(dot) => filename.split_at(dot),
+            None => (filename, ""),
+        };
+        let mut stem_len = max_bytes.saturating_sub(extension.len()).min(stem.len());
+        while !stem.is_char_boundary(stem_len) {
+            stem_len -= 1;
+        }
+        format!("{}{}", stem[..stem_len].trim_end(), extension)
     }
 
     pub fn map<'a>(&self, filename: &'a str) -> String {
-        match self {
-            Self::Default => filename.replace("/", "\u{2215}"),
-            Self::Ntfs(matcher) => matcher.replace_all(
-                filename,
+        let filename: Cow<'a, str> = if self.normalize_unicode {
+            Cow::Owned(filename.nfc().collect())
+        } else {
+            Cow::Borrowed(filename)
+        };
+        match &self.ntfs {
+            None => filename.replace("/", "\u{2215}"),
+            Some(matcher) => avoid_reserved_name(&matcher.replace_all(
+                &filename,
                 &[
                     "\u{2215}", "\u{02d0}", "\u{0294}", "\u{00a8}", "\u{01c0}", "\u{04ff}",
                 ],
+            )),
+        }
+    }
+}
+
+/// Longest file name written, in bytes. Most filesystems allow 255 bytes,
+/// and this leaves room for the temporary suffixes some tools add.
+const MAX_FILE_NAME_BYTES: usize = 200;
+
+/// Device names that Windows reserves, with or without an extension.
+const WINDOWS_RESERVED_NAMES: &[&str] = &[
+    "CON", "PRN", "AUX", "NUL", "COM0", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7",
+    "COM8", "COM9", "LPT0", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// Makes a file name usable on Windows: strips the trailing dots and spaces
+/// that Windows silently drops, and appends an underscore to the stem of a
+/// reserved device name.
+fn avoid_reserved_name(filename: &str) -> String {
+    let mut filename = filename.trim_end_matches(['.', ' ']).to_string();
+    let stem_len = filename.find('.').unwrap_or(filename.len());
+    let stem = &filename[..stem_len];
+    if WINDOWS_RESERVED_NAMES
+        .iter()
+        .any(|reserved| stem.eq_ignore_ascii_case(reserved))
+    {
+        filename.insert(stem_len, '_');
+    }
+    filename
+}
+
+/// Everything needed to decide where a rip's output files will go.
+pub struct RipContext<'a> {
+    path_sanitizer: &'a PathSanitizer,
+    release: &'a mb::Release,
+    medium: &'a mb::Media,
+    encoder: &'a dyn encode::AudioEncoder,
+    filename_template: Option<&'a template::Template>,
+    /// Goes between the track number and the title when there is no
+    /// `filename_template`.
+    track_separator: &'a str,
+    /// Width that track and disc numbers are zero-padded to when there is no
+    /// `filename_template`.
+    track_pad_width: usize,
+    album_dir: PathBuf,
+    cover_art_file_name: Option<String>,
+    /// Additional cover art images, relative to `album_dir`.
+    album_art_paths: Vec<PathBuf>,
+    /// Sector count of each track on the disc, as reported by the TOC.
+    track_sectors: Vec<(u32, u32)>,
+    /// Length of the pre-gap before track 1, which `--rip-htoa` rips as a
+    /// hidden track, as reported by the TOC.
+    htoa_sectors: u32,
+}
+
+impl<'a> RipContext<'a> {
+    fn multi_disc(&self) -> bool {
+        self.release.media.len() > 1
+    }
+
+    /// Zero-pads a track or disc number for a file name.
+    fn pad(&self, number: u32) -> String {
+        format!("{:0width$}", number, width = self.track_pad_width)
+    }
+
+    fn track_file_name(&self, track_num: u32) -> String {
+        let placeholder;
+        let mb_track_info = match self.medium.tracks.get(track_num as usize - 1) {
+            Some(track) => track,
+            None => {
+                placeholder = mb::Track::placeholder(track_num);
+                &placeholder
+            }
+        };
+        let title = tags::resolve_track_title(mb_track_info, track_num);
+        let file_name = if let Some(template) = self.filename_template {
+            template.render(&template::Values {
+                track: Some(track_num),
+                disc: Some(self.medium.position),
+                title: &title,
+                artist: &mb_track_info.artist_string(),
+                album: &self.release.title,
+                year: self.release.release_year(),
+                version: &mb_track_info.recording.disambiguation,
+                country: self.release.country.as_deref().unwrap_or_default(),
+                catalog: self.release.catalog_number().unwrap_or_default(),
+            })
+        } else if self.multi_disc() {
+            format!(
+                "{}-{}{}{}",
+                self.pad(self.medium.position),
+                self.pad(track_num),
+                self.track_separator,
+                title
+            )
+        } else {
+            format!("{}{}{}", self.pad(track_num), self.track_separator, title)
+        };
+        let file_name = file_name + self.encoder.file_extension();
+        PathSanitizer::truncate(&self.path_sanitizer.map(&file_name), MAX_FILE_NAME_BYTES)
+    }
+
+    /// Playlist entry for a track, titled from MusicBrainz when `track` is
+    /// known.
+    fn playlist_entry(
+        &self,
+        track: Option<&mb::Track>,
+        track_num: u32,
+        file_name: String,
+        duration: u32,
+    ) -> playlist::Entry {
+        let (artist, title) = match track {
+            Some(track) => (
+                track.artist_string(),
+                tags::resolve_track_title(track, track_num).into_owned(),
+            ),
+            None => (
+                self.release.artist_string(),
+                format!("Track {:02}", track_num),
             ),
+        };
+        playlist::Entry {
+            path: self.album_dir.join(file_name),
+            duration,
+            artist,
+            title,
+        }
+    }
+
+    /// Name of a track cut with `--split-at-silence`. The MusicBrainz titles
+    /// are only used when the number of tracks matches the release.
+    fn split_file_name(&self, split_num: u32, split_count: usize) -> String {
+        if split_count == self.medium.tracks.len() {
+            return self.track_file_name(split_num);
         }
+        let file_name = if self.multi_disc() {
+            format!("{}-{}", self.pad(self.medium.position), self.pad(split_num))
+        } else {
+            self.pad(split_num)
+        };
+        let file_name = file_name + self.encoder.file_extension();
+        PathSanitizer::truncate(&self.path_sanitizer.map(&file_name), MAX_FILE_NAME_BYTES)
+    }
+
+    /// Name of the hidden track before track 1, ripped with `--rip-htoa`.
+    fn htoa_file_name(&self) -> String {
+        let file_name = if self.multi_disc() {
+            format!(
+                "{}-{}{}Hidden Track",
+                self.pad(self.medium.position),
+                self.pad(0),
+                self.track_separator
+            )
+        } else {
+            format!("{}{}Hidden Track", self.pad(0), self.track_separator)
+        };
+        let file_name = file_name + self.encoder.file_extension();
+        PathSanitizer::truncate(&self.path_sanitizer.map(&file_name), MAX_FILE_NAME_BYTES)
+    }
+
+    /// Name of a file describing the whole disc, after the release title.
+    fn disc_file_name(&self, extension: &str) -> String {
+        let file_name = if self.multi_disc() {
+            format!(
+                "{} (Disc {}){}",
+                self.release.title, self.medium.position, extension
+            )
+        } else {
+            format!("{}{}", self.release.title, extension)
+        };
+        self.path_sanitizer.map(&file_name)
+    }
+
+    fn cue_file_name(&self) -> String {
+        self.disc_file_name(".cue")
+    }
+
+    fn nfo_file_name(&self) -> String {
+        self.disc_file_name(".nfo")
+    }
+
+    /// Name of the M3U playlist, which covers every disc of the release with
+    /// `combined`.
+    fn playlist_file_name(&self, combined: bool) -> String {
+        let disc = Some(self.medium.position).filter(|_| self.multi_disc() && !combined);
+        playlist::file_name(disc)
     }
 }
 
-fn main() -> anyhow::Result<()> {
-    let args = Cli::parse();
+/// Parses a comma-separated list of track numbers and ranges, such as
+/// `1,3,5-8`.
+fn parse_track_list(list: &str) -> anyhow::Result<BTreeSet<u32>> {
+    let mut tracks = BTreeSet::new();
+    for item in list.split(',') {
+        let item = item.trim();
+        let parse = |number: &str| -> anyhow::Result<u32> {
+            match number.trim().parse() {
+                Ok(0) | Err(_) => bail!("invalid track number {:?}", number),
+                Ok(number) => Ok(number),
+            }
+        };
+        match item.split_once('-') {
+            Some((first, last)) => {
+                let (first, last) = (parse(first)?, parse(last)?);
+                if first > last {
+                    bail!("invalid track range {:?}", item);
+                }
+                tracks.extend(first..=last);
+            }
+            None => {
+                tracks.insert(parse(item)?);
+            }
+        }
+    }
+    Ok(tracks)
+}
 
-    let path_sanitizer = if args.ntfs_filenames {
-        PathSanitizer::default()
-    } else {
-        PathSanitizer::ntfs()
-    };
+fn artist_subdir_name(release: &mb::Release, sort: bool) -> String {
+    let album_artist = release.album_artist_string();
+    if album_artist != release.artist_string() {
+        return album_artist;
+    }
+    match release.artist_credit.first() {
+        Some(credit) if sort => {
+            if credit.artist.sort_name.is_empty() {
+                credit.artist.name.clone()
+            } else {
+                credit.artist.sort_name.clone()
+            }
+        }
+        _ => album_artist,
+    }
+}
 
-    let disc_device = args.disc_device.as_deref().unwrap_or("/dev/cdrom");
+fn open_drive(disc_device: &str, max_speed: Option<u32>) -> anyhow::Result<CdromParanoia> {
+    let c_disc_device = CString::new(disc_device);
+    let cdrom = CdromDrive::identify(
+        c_disc_device.unwrap().as_c_str(),
+        cdparanoia::Verbosity::PrintIt,
+    )
+    .context("failed to identify CD drive")?;
+    cdrom.set_verbosity(cdparanoia::Verbosity::LogIt, cdparanoia::Verbosity::LogIt);
+    cdrom.open().context("failed to open CD drive")?;
+    if let Some(speed) = max_speed {
+        // Not fatal: many drives don't support setting the speed.
+        if let Err(error) = cdrom.set_speed(speed as i32) {
+            warn!("Failed to set the drive speed to {}x: {}", speed, error);
+        }
+    }
+    let paranoia = CdromParanoia::init(cdrom);
 
-    let disc_info = DiscId::read(Some(disc_device))?;
-    let disc_id = disc_info.id();
-    let toc = disc_info.toc_string();
+    log_drive_messages(paranoia.drive());
+    Ok(paranoia)
+}
+
+fn log_drive_messages(drive: &CdromDrive) {
+    if let Some(error) = drive.errors() {
+        for line in error.to_string_lossy().lines() {
+            debug!("{}", line);
+        }
+    }
+    // Messages are reported for nearly every sector read.
+    if let Some(message) = drive.messages() {
+        for line in message.to_string_lossy().lines() {
+            trace!("{}", line);
+        }
+    }
+}
+
+fn show_submission_url(url: &str) {
+    println!();
+    println!("=== MusicBrainz disc ID submission URL ===");
+    println!("{}", url);
+    println!("==========================================");
+    println!();
+
+    #[cfg(feature = "clipboard")]
+    {
+        use clipboard::{ClipboardContext, ClipboardProvider};
+
+        let copied = ClipboardProvider::new()
+            .and_then(|mut clipboard: ClipboardContext| clipboard.set_contents(url.to_string()));
+        match copied {
+            Ok(()) => info!("Submission URL copied to clipboard"),
+            Err(error) => warn!("Failed to copy submission URL to clipboard: {}", error),
+        }
+    }
+}
+
+/// Helps the user find the release of a disc whose ID is not in the database:
+/// by searching for it, or by submitting the disc ID and looking it up again.
+fn find_unknown_disc(
+    mb_client: &mb::Client,
+    disc_info: &DiscId,
+    includes: &[&str],
+) -> anyhow::Result<Vec<mb::Release>> {
+    let console_theme = ColorfulTheme::default();
+
+    loop {
+        let search = Confirm::with_theme(&console_theme)
+            .with_prompt("Search MusicBrainz by artist and title instead?")
+            .default(true)
+            .interact()?;
+        if search {
+            let releases = search_releases(mb_client)?;
+            if !releases.is_empty() {
+                return Ok(releases);
+            }
+        }
+
+        let submit = Confirm::with_theme(&console_theme)
+            .with_prompt("Open the MusicBrainz disc ID submission page in your browser?")
+            .default(true)
+            .interact()?;
+        if !submit {
+            bail!("No release found for this Disc ID. Please submit it to the database.");
+        }
+        let url = disc_info.submission_url();
+        if let Err(error) = open_in_browser(&url) {
+            warn!("Failed to open a browser: {:#}", error);
+            show_submission_url(&url);
+        }
+
+        let retry = Confirm::with_theme(&console_theme)
+            .with_prompt("Look up the disc ID again once it has been submitted?")
+            .default(true)
+            .interact()?;
+        if !retry {
+            bail!("No release found for this Disc ID.");
+        }
+        let releases = disc_releases(mb_client, &disc_info.id(), includes)?;
+        if !releases.is_empty() {
+            return Ok(releases);
+        }
+        info!("Still no release found for this Disc ID.");
+    }
+}
+
+/// Looks up the releases with `disc_id`. A disc ID that MusicBrainz does not
+/// know has no releases.
+fn disc_releases(
+    mb_client: &mb::Client,
+    disc_id: &str,
+    includes: &[&str],
+) -> anyhow::Result<Vec<mb::Release>> {
+    match mb::DiscId::lookup_with_includes(mb_client, disc_id, includes) {
+        Ok(mb_info) => Ok(mb_info.releases),
+        Err(mb::MbError::NotFound) => Ok(Vec::new()),
+        Err(mb::MbError::RateLimited) => {
+            bail!("MusicBrainz is rate limiting requests; try again in a few minutes")
+        }
+        Err(error @ mb::MbError::NetworkError(_)) => Err(anyhow::Error::new(error)
+            .context("Could not reach MusicBrainz; check your network connection")),
+        Err(error @ mb::MbError::ParseError(_)) => Err(anyhow::Error::new(error).context(
+            "MusicBrainz sent a response ripoff does not understand; \
+            please report this as a bug",
+        )),
+        Err(error) => Err(error.into()),
+    }
+}
+
+/// Looks up `disc_id` on MusicBrainz and rebuilds the disc's TOC from it, so
+/// that `--test-disc-id` can stand in for a disc in the drive.
+fn test_disc_toc(
+    mb_client: &mb::Client,
+    disc_id: &str,
+    includes: &[&str],
+) -> anyhow::Result<DiscId> {
+    let releases = disc_releases(mb_client, disc_id, includes)?;
+    let disc = releases
+        .iter()
+        .flat_map(|release| &release.media)
+        .flat_map(|medium| &medium.discs)
+        .find(|disc| disc.id == disc_id)
+        .with_context(|| format!("Disc ID {} is not on MusicBrainz", disc_id))?;
+    discid::from_musicbrainz(disc)
+}
 
-    println!("Disc ID: {:?}", disc_id);
-    println!("TOC: {:?}", toc);
-    println!("Submit via: {}", disc_info.submission_url());
+/// Asks the user for an artist and title and searches for them.
+fn search_releases(mb_client: &mb::Client) -> anyhow::Result<Vec<mb::Release>> {
+    let console_theme = ColorfulTheme::default();
 
-    let mb_client = mb::Client::new();
-    let mb_info = mb::DiscId::lookup(&mb_client, &disc_id)?;
+    let artist: String = Input::with_theme(&console_theme)
+        .with_prompt("Artist")
+        .interact_text()?;
+    let title: String = Input::with_theme(&console_theme)
+        .with_prompt("Album title")
+        .interact_text()?;
 
-    let mut releases = mb_info.releases;
+    let releases = mb::Release::search(mb_client, &artist, &title)?;
     if releases.is_empty() {
-        bail!("No release found for this Disc ID. Please submit it to the database.");
+        info!("No release found for {:?} by {:?}.", title, artist);
+    }
+    Ok(releases)
+}
+
+fn open_in_browser(url: &str) -> anyhow::Result<()> {
+    let status = std::process::Command::new("xdg-open")
+        .arg(url)
+        .status()
+        .context("failed to run xdg-open")?;
+    if !status.success() {
+        bail!("xdg-open exited with {}", status);
     }
+    Ok(())
+}
 
+/// Finds the medium of `release` that holds the disc. Releases found by
+/// searching may not have the disc ID attached, in which case the medium is
+/// picked by its number of tracks, asking the user if that is ambiguous.
+fn select_medium<'a>(
+    release: &'a mb::Release,
+    disc_id: &str,
+    track_count: u32,
+) -> anyhow::Result<&'a mb::Media> {
+    if let Some(medium) = release
+        .media
+        .iter()
+        .find(|medium| medium.discs.iter().any(|disc| disc.id == disc_id))
+    {
+        return Ok(medium);
+    }
+
+    let candidates: Vec<&mb::Media> = release
+        .media
+        .iter()
+        .filter(|medium| medium.track_count == track_count)
+        .collect();
+    match candidates.as_slice() {
+        [] => bail!(
+            "No medium of the release has {} tracks like the disc",
+            track_count
+        ),
+        [medium] => Ok(medium),
+        _ => {
+            let choices: Vec<String> = candidates
+                .iter()
+                .map(|medium| match medium.title.as_str() {
+                    "" => format!("{} {}", medium.format, medium.position),
+                    title => format!("{} {}: {}", medium.format, medium.position, title),
+                })
+                .collect();
+            let index = Select::with_theme(&ColorfulTheme::default())
+                .with_prompt("Which medium is this disc?")
+                .items(&choices)
+                .interact()?;
+            Ok(candidates[index])
+        }
+    }
+}
+
+/// Keeps only the releases where `field` is `value`, unless there are none.
+fn prefer_releases(
+    releases: &mut Vec<mb::Release>,
+    value: &str,
+    field: impl Fn(&mb::Release) -> Option<&str>,
+) {
+    let matches = |release: &mb::Release| {
+        field(release).is_some_and(|field| field.eq_ignore_ascii_case(value))
+    };
+    if releases.iter().any(matches) {
+        releases.retain(matches);
+    } else {
+        warn!(
+            "No {} release matches this Disc ID; showing all releases",
+            value
+        );
+    }
+}
+
+/// Names shared by different artists credited on `releases`.
+fn ambiguous_artist_names(releases: &[mb::Release]) -> HashSet<&str> {
+    let mut ids_by_name: HashMap<&str, HashSet<&str>> = HashMap::new();
+    for credit in releases.iter().flat_map(|release| &release.artist_credit) {
+        ids_by_name
+            .entry(credit.artist.name.as_str())
+            .or_default()
+            .insert(credit.artist.id.as_str());
+    }
+    ids_by_name
+        .into_iter()
+        .filter(|(_, ids)| ids.len() > 1)
+        .map(|(name, _)| name)
+        .collect()
+}
+
+/// Asks the user which of the releases matching the disc is theirs.
+fn select_release(mut releases: Vec<mb::Release>) -> anyhow::Result<mb::Release> {
     let console_theme = ColorfulTheme::default();
 
     const DISAMBIGUATIONS: &[(&str, fn(&mb::Release) -> Option<String>)] = &[
         ("album", |release| {
-            Some(format!("{} - {}", release.artist_string(), release.title))
+            let album = format!("{} - {}", release.artist_string(), release.title);
+            Some(match release.type_string() {
+                Some(release_type) => format!("{} [{}]", album, release_type),
+                None => album,
+            })
         }),
         ("catalog number", |release| {
             release.catalog_number().map(str::to_string)
@@ -134,25 +1148,24 @@ fn main() -> anyhow::Result<()> {
     }
 
     // Confirm selection from remaining releases:
+    let ambiguous_artists = ambiguous_artist_names(&releases);
     let release_summaries: Vec<String> = releases
         .iter()
         .map(|release| {
             let mbid = &release.id;
-            let catalog_number = release
-                .label_info
-                .get(0)
-                .and_then(|label| label.catalog_number.as_deref())
-                .unwrap_or("");
+            let catalog_number = release.catalog_number().unwrap_or("");
             let barcode = release.barcode.as_deref().unwrap_or("");
-            let artist = release.artist_string();
-            let title = &release.title;
+            let artist = release.disambiguated_artist_string(&ambiguous_artists);
+            let title = release.disambiguated_title();
+            let release_type = release.type_string().unwrap_or_default();
             format!(
                 "MBID: {}\
                 \n  - Artist: {}\
                 \n  - Title: {}\
+                \n  - Type: {}\
                 \n  - Catalog Number: {}\
                 \n  - Barcode: {}",
-                mbid, artist, title, catalog_number, barcode
+                mbid, artist, title, release_type, catalog_number, barcode
             )
         })
         .collect();
@@ -161,22 +1174,424 @@ fn main() -> anyhow::Result<()> {
         .with_prompt("Confirm release:")
         .items(&release_summaries)
         .interact()?;
-    let selected_release = &releases[selected_index];
-    let multi_disc = selected_release.media.len() > 1;
+    Ok(releases.swap_remove(selected_index))
+}
+
+fn main() -> anyhow::Result<()> {
+    let matches = Cli::command().get_matches();
+    let mut args = Cli::from_arg_matches(&matches).unwrap_or_else(|error| error.exit());
+    let config = config::Config::load(args.config.as_deref())?;
+    config.apply_to(&mut args, &matches)?;
+    if args.json {
+        report::redirect_stdout()?;
+    }
+    init_logging(args.verbose);
+    interrupt::install_handler()?;
+
+    let mut path_sanitizer = if args.ntfs_filenames {
+        PathSanitizer::ntfs()
+    } else {
+        PathSanitizer::default()
+    };
+    if args.no_unicode_normalize {
+        path_sanitizer = path_sanitizer.without_unicode_normalization();
+    }
+
+    let disc_device = args.disc_device.as_deref().unwrap_or("/dev/cdrom");
+
+    if let Some(Command::ListDiscs {
+        release_id,
+        check_my_disc,
+    }) = &args.command
+    {
+        let my_disc_id = if *check_my_disc {
+            Some(DiscId::read(Some(disc_device))?.id())
+        } else {
+            None
+        };
+        return list_discs::run(&mb_client(&args), release_id, my_disc_id.as_deref());
+    }
+    if let Some(Command::Batch { file }) = &args.command {
+        return batch::run(&args, &config, &path_sanitizer, disc_device, file);
+    }
+
+    if args.list_devices {
+        return drive::list_devices();
+    }
+    if args.device_speed_test {
+        return speed_test::run(disc_device, args.sectors, args.max_speed);
+    }
+    let output_path = args
+        .output_path
+        .as_ref()
+        .expect("output path is required when ripping");
+
+    rip(
+        &args,
+        &config,
+        &path_sanitizer,
+        disc_device,
+        output_path,
+        None,
+    )
+}
+
+fn mb_client(args: &Cli) -> mb::Client {
+    let mut builder = mb::ClientBuilder::new()
+        .root_url(&args.mb_root_url)
+        .timeout(Duration::from_secs(args.mb_timeout_secs))
+        .retry_count(args.mb_retries)
+        .refresh_cache(args.refresh_cache)
+        .cache_ttl(Duration::from_secs(
+            args.mb_cache_ttl_days.saturating_mul(24 * 60 * 60),
+        ))
+        .ignore_cache_ttl(args.skip_mb_if_cached);
+    if let Some(cache_dir) = &args.mb_cache_dir {
+        builder = builder.cache_dir(cache_dir.clone());
+    }
+    builder.build()
+}
+
+/// Sends log output to stdout, at the level set by `RUST_LOG` or otherwise
+/// by `--verbose`.
+fn init_logging(verbose: bool) {
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| {
+        EnvFilter::new(if verbose {
+            "ripoff=debug"
+        } else {
+            "ripoff=info"
+        })
+    });
+    tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_target(false)
+        .without_time()
+        .init();
+}
+
+/// Rips the disc in `disc_device` into `output_path`.
+///
+/// If `release_id` is given, the rip runs unattended: that release is used
+/// without prompting, and an existing album directory is an error rather
+/// than a question. With `--json`, the rip is unattended as well, and the only
+/// release matching the disc is used.
+fn rip(
+    args: &Cli,
+    config: &config::Config,
+    path_sanitizer: &PathSanitizer,
+    disc_device: &str,
+    output_path: &Path,
+    release_id: Option<&str>,
+) -> anyhow::Result<()> {
+    let mb_client = mb_client(args);
+    let mut includes = mb::DEFAULT_DISC_INCLUDES.to_vec();
+    for include in &args.mb_includes {
+        if !includes.contains(&include.as_str()) {
+            includes.push(include);
+        }
+    }
+
+    let disc_info = match (&args.test_disc_id, &args.disc_toc_file) {
+        (Some(disc_id), _) => test_disc_toc(&mb_client, disc_id, &includes)?,
+        (None, Some(path)) => discid::load_toc_file(path)?,
+        (None, None) => DiscId::read(Some(disc_device))?,
+    };
+    let disc_id = disc_info.id();
+    let toc = disc_info.toc_string();
+
+    info!("Disc ID: {:?}", disc_id);
+    info!("TOC: {:?}", toc);
+    info!("Submit via: {}", disc_info.submission_url());
+
+    if args.show_submission_url {
+        show_submission_url(&disc_info.submission_url());
+    }
+
+    let mut releases = disc_releases(&mb_client, &disc_id, &includes)?;
+
+    let unattended = release_id.is_some() || args.json;
+
+    if releases.is_empty() {
+        if unattended {
+            bail!("No release found for this Disc ID. Please submit it to the database.");
+        }
+        info!("No release found for this Disc ID.");
+        releases = find_unknown_disc(&mb_client, &disc_info, &includes)?;
+    }
+
+    let release = match release_id {
+        Some(release_id) => releases
+            .into_iter()
+            .find(|release| release.id == release_id)
+            .with_context(|| format!("Release {} does not match the disc", release_id))?,
+        None => {
+            let mut releases = releases;
+            if let Some(status) = args.mb_status {
+                prefer_releases(&mut releases, status.mb_name(), |release| {
+                    release.status.as_deref()
+                });
+            }
+            if let Some(release_type) = args.mb_type {
+                prefer_releases(&mut releases, release_type.mb_name(), |release| {
+                    release
+                        .release_group
+                        .as_ref()
+                        .and_then(|release_group| release_group.primary_type.as_deref())
+                });
+            }
+            if let Some(filter) = &args.mb_release_filter {
+                releases.retain(|release| filter.matches(release));
+                if releases.is_empty() {
+                    bail!("No release for this Disc ID matches --mb-release-filter");
+                }
+            }
+            if args.json {
+                if releases.len() > 1 {
+                    bail!(
+                        "{} releases match this Disc ID; narrow them down with \
+                        --mb-release-filter",
+                        releases.len()
+                    );
+                }
+                releases.remove(0)
+            } else {
+                select_release(releases)?
+            }
+        }
+    };
+    let mut release = release;
+    info!("Release: {}", release.url());
+
+    let disc_track_count = (disc_info.last_track_num() - disc_info.first_track_num() + 1) as u32;
+    let medium_position = select_medium(&release, &disc_id, disc_track_count)?.position;
+
+    let mb_originals = if args.edit_metadata {
+        let medium = release
+            .media
+            .iter_mut()
+            .find(|medium| medium.position == medium_position)
+            .expect("medium was just selected");
+        edit::edit_tracks(medium)?
+    } else {
+        Vec::new()
+    };
+
+    let selected_release = &release;
     let mb_disc_info = selected_release
         .media
         .iter()
-        .find(|medium| medium.discs.iter().any(|disc| disc.id == disc_id))
-        .context("Cannot find medium that matches the disc ID")?;
+        .find(|medium| medium.position == medium_position)
+        .expect("medium was just selected");
+    tags::warn_empty_titles(mb_disc_info);
+
+    if args.print_cddb {
+        let freedb_id =
+            u32::from_str_radix(&disc_info.freedb_id(), 16).context("invalid freedb disc ID")?;
+        println!();
+        print!(
+            "{}",
+            cddb::format_xmcd_entry(selected_release, mb_disc_info, freedb_id)
+        );
+        println!();
+    }
+
+    // Compilations stay under "Various Artists", which has no sort name.
+    let album_artist = selected_release.album_artist_string();
+    let dir_artist = if args.sort_artist && album_artist == selected_release.artist_string() {
+        selected_release.sort_artist_string()
+    } else {
+        album_artist
+    };
+    let dir_name = path_sanitizer.map(&match &args.dirname_template {
+        Some(template) => template.render(&template::Values {
+            artist: &dir_artist,
+            album: &selected_release.title,
+            year: selected_release.release_year(),
+            country: selected_release.country.as_deref().unwrap_or_default(),
+            catalog: selected_release.catalog_number().unwrap_or_default(),
+            ..Default::default()
+        }),
+        None => format!(
+            "{}{}{}",
+            dir_artist, args.dir_separator, selected_release.title
+        ),
+    });
+
+    let mut output_path = output_path.to_path_buf();
+    if args.artist_subdir {
+        output_path.push(path_sanitizer.map(&artist_subdir_name(
+            selected_release,
+            args.artist_subdir_sort || args.sort_artist,
+        )));
+    }
+
+    // An empty name would make the output directory itself the album
+    // directory, which the overwrite prompt could then delete.
+    template::check_dir_name(&dir_name)?;
+    let album_dir = output_path.join(&dir_name);
+
+    let has_front_cover = selected_release
+        .cover_art_archive
+        .as_ref()
+        .is_some_and(|caa| caa.front);
+    let cover_art_output = if args.no_cover_art {
+        CoverArtOutput::None
+    } else {
+        args.cover_art_output
+    };
+    let cover_art = if cover_art_output != CoverArtOutput::None && has_front_cover {
+        match caa::fetch_front(&selected_release.id) {
+            Ok(Some(cover_art)) => Some(cover_art),
+            Ok(None) => {
+                warn!("Front cover art not found in the Cover Art Archive");
+                None
+            }
+            Err(error) => {
+                warn!("Failed to fetch cover art: {:#}", error);
+                None
+            }
+        }
+    } else {
+        None
+    };
+    let cover_art_file_name =
+        cover_art
+            .as_ref()
+            .filter(|_| cover_art_output.file())
+            .map(|cover_art| {
+                path_sanitizer.map(
+                    &args
+                        .cover_art_filename
+                        .clone()
+                        .unwrap_or_else(|| format!("cover.{}", cover_art.extension())),
+                )
+            });
+    let embedded_cover_art = cover_art.as_ref().filter(|_| cover_art_output.embedded());
+
+    let has_artwork = selected_release
+        .cover_art_archive
+        .as_ref()
+        .is_some_and(|caa| caa.artwork);
+    let album_art = if args.cover_art_type == CoverArtType::All && has_artwork {
+        match caa::list_images(&selected_release.id) {
+            Ok(images) => caa::image_paths(
+                images,
+                args.albumart_dir.as_deref(),
+                args.cover_art_name_by_index,
+                path_sanitizer,
+            ),
+            Err(error) => {
+                warn!("Failed to list cover art: {:#}", error);
+                Vec::new()
+            }
+        }
+    } else {
+        Vec::new()
+    };
 
-    let dir_name = path_sanitizer.map(&format!(
-        "{} - {}",
-        selected_release.artist_string(),
-        selected_release.title
+    let mut album_tags = tags::release_tags(selected_release);
+    album_tags.extend(tags::medium_tags(selected_release, mb_disc_info, &disc_id));
+    album_tags.push((
+        "RIPPINGAPPLICATION".to_string(),
+        format!("ripoff {}", env!("CARGO_PKG_VERSION")),
     ));
+    let drive_name = if args.raw_pcm_input.is_none() && args.test_disc_id.is_none() {
+        let drive_name = drive::identification(disc_device);
+        match &drive_name {
+            Some(drive) => {
+                info!("Drive: {}", drive);
+                album_tags.push(("RIPPINGDRIVE".to_string(), drive.clone()));
+            }
+            None => warn!("Failed to identify drive model"),
+        }
+        drive_name
+    } else {
+        None
+    };
+    if let Some(label) = selected_release
+        .label_info
+        .first()
+        .and_then(|label_info| label_info.label.as_ref())
+    {
+        match mb::Label::lookup(&mb_client, &label.id) {
+            Ok(label) => album_tags.extend(tags::label_tags(&label)),
+            Err(error) => warn!("Failed to look up label: {:#}", error),
+        }
+    }
+    if let Some(notes) = &args.disc_notes {
+        info!("Disc notes: {}", notes);
+        album_tags.push(tags::comment_tag(notes));
+    }
+
+    let reference_crcs = args
+        .compare_crc_file
+        .as_deref()
+        .map(crc::load_reference)
+        .transpose()?;
 
-    let album_dir = args.output_path.join(&dir_name);
-    if album_dir.exists() {
+    let encoder = args.format.encoder(args.compression_level, args.verify);
+    if !encoder.supports_metadata() {
+        warn!("Tags and cover art cannot be fully embedded in this output format");
+    }
+
+    let ctx = RipContext {
+        path_sanitizer,
+        encoder: encoder.as_ref(),
+        filename_template: args.filename_template.as_ref(),
+        track_separator: &args.track_separator,
+        track_pad_width: args.track_pad_width.into(),
+        release: selected_release,
+        medium: mb_disc_info,
+        album_dir: album_dir.clone(),
+        cover_art_file_name: cover_art_file_name.clone(),
+        album_art_paths: album_art.iter().map(|(path, _)| path.clone()).collect(),
+        track_sectors: disc_info
+            .tracks()
+            .map(|track| (track.number as u32, track.sectors as u32))
+            .collect(),
+        htoa_sectors: disc_info.tracks().next().map_or(0, |track| {
+            (track.offset as u32).saturating_sub(source::LEAD_IN_SECTORS)
+        }),
+    };
+
+    if args.output_tree_info {
+        println!("{}", preview::preview_output_tree(&ctx, args));
+    }
+
+    if args.dry_run {
+        preview::print_dry_run(&ctx, args.resume);
+        return Ok(());
+    }
+
+    if args.test_disc_id.is_some() {
+        preview::print_dry_run(&ctx, args.resume);
+        preview::print_track_tags(&ctx, &album_tags, args.genre_count);
+        return Ok(());
+    }
+
+    let mut source: Box<dyn source::AudioSource> = match &args.raw_pcm_input {
+        Some(path) => {
+            let disc = mb_disc_info
+                .discs
+                .iter()
+                .find(|disc| disc.id == disc_id)
+                .context("--raw-pcm-input needs a release that this disc ID is attached to")?;
+            Box::new(source::RawPcm::open(path, disc)?)
+        }
+        None => Box::new(open_drive(disc_device, args.max_speed)?),
+    };
+    paranoia::apply_paranoia_mode(source.as_mut(), &args.paranoia_mode)?;
+
+    preview::print_track_table(mb_disc_info, source.as_ref())?;
+
+    if args.retry_failed {
+        if !album_dir.exists() {
+            bail!("Cannot retry failed tracks: {:?} does not exist", album_dir);
+        }
+    } else if album_dir.exists() && !args.resume {
+        if unattended {
+            bail!("Output path already exists: {:?}", album_dir);
+        }
         let overwrite = Confirm::new()
             .with_prompt(&format!(
                 "Output path already exists: {:?}\nOverwrite?",
@@ -189,113 +1604,701 @@ fn main() -> anyhow::Result<()> {
     }
     std::fs::create_dir_all(&album_dir)?;
 
-    let c_disc_device = CString::new(disc_device);
-    let cdrom = CdromDrive::identify(
-        c_disc_device.unwrap().as_c_str(),
-        cdparanoia::Verbosity::PrintIt,
-    )
-    .context("failed to identify CD drive")?;
-    cdrom.set_verbosity(cdparanoia::Verbosity::LogIt, cdparanoia::Verbosity::LogIt);
-    cdrom.open().context("failed to open CD drive")?;
-    let mut paranoia = CdromParanoia::init(cdrom);
-    paranoia.set_mode(ParanoiaMode::FULL);
-
-    if let Some(error) = paranoia.drive().errors() {
-        for line in error.to_string_lossy().lines() {
-            println!("{}", line);
+    let mut rip_status = status::RipStatus::load(&album_dir)?;
+    let retry_tracks = if args.retry_failed {
+        let failed = rip_status.failed_tracks();
+        if failed.is_empty() {
+            info!("No failed tracks to retry");
+            return Ok(());
         }
+        Some(failed)
+    } else {
+        None
+    };
+
+    if let (Some(cover_art), Some(file_name)) = (&cover_art, &cover_art_file_name) {
+        std::fs::write(album_dir.join(file_name), &cover_art.data)
+            .context("failed to save cover art")?;
     }
-    if let Some(message) = paranoia.drive().messages() {
-        for line in message.to_string_lossy().lines() {
-            println!("{}", line);
+    for (path, image) in &album_art {
+        let path = album_dir.join(path);
+        match caa::fetch(&image.image) {
+            Ok(Some(art)) => {
+                if let Some(parent) = path.parent() {
+                    std::fs::create_dir_all(parent)?;
+                }
+                std::fs::write(&path, &art.data).context("failed to save cover art")?;
+            }
+            Ok(None) => warn!("Cover art image not found: {}", image.image),
+            Err(error) => warn!("Failed to fetch cover art: {:#}", error),
         }
     }
 
-    let track_count = paranoia.drive().tracks()?;
+    let track_count = source.tracks()?;
+    let mut audio_tracks = BTreeSet::new();
     for track_num in 1..=track_count {
-        if !paranoia.drive().track_audiop(track_num)? {
-            println!("WARN: Track {} is not an audio track; skipping", track_num);
-            continue;
+        if is_audio_track(source.as_ref(), track_num)? {
+            audio_tracks.insert(track_num);
+        }
+    }
+    // AccurateRip treats the last audio track as the last track, even if a
+    // data track follows it.
+    let last_audio_track = audio_tracks.last().copied().unwrap_or(track_count);
+    // MusicBrainz does not list the data track of an enhanced CD.
+    if audio_tracks.len() != mb_disc_info.tracks.len() {
+        warn!(
+            "The disc has {} audio tracks, but MusicBrainz lists {}; \
+            tracks it does not list will get generic names",
+            audio_tracks.len(),
+            mb_disc_info.tracks.len()
+        );
+    }
+    if let Some(&last) = args.tracks.as_ref().and_then(|tracks| tracks.last()) {
+        if last > track_count {
+            bail!(
+                "--tracks includes track {}, but the disc only has {} tracks",
+                last,
+                track_count
+            );
         }
+    }
+    let mut ripped_tracks = Vec::new();
+    // Names of the files written in this run, for the checksum manifest.
+    let mut ripped_files = Vec::new();
+    let mut playlist_entries = Vec::new();
+    let mut track_events = BTreeMap::new();
+    let mut accuraterip_checksums = BTreeMap::new();
+    let mut track_reports = Vec::new();
+    let mut htoa_file = None;
+    let mut failed_tracks = Vec::new();
 
-        let start_time = Instant::now();
+    // With --split-at-silence, the whole disc is read into one buffer and only
+    // cut into tracks once the silences in it are known.
+    let mut disc_samples = Vec::new();
+    let mut disc_channels = None;
+    let mut disc_preemphasis = false;
 
-        let first_sector = paranoia.drive().track_first_sector(track_num)?;
-        let last_sector = paranoia.drive().track_last_sector(track_num)?;
-        let total_sectors = last_sector - first_sector + 1;
-        let track_channels = paranoia.drive().track_channels(track_num)?;
-        let track_duration =
-            total_sectors as u32 * CD_FRAMEWORDS / (CD_SAMPLE_RATE * track_channels);
+    // Reads shifted by --read-offset cannot go past the lead-out.
+    let last_readable_sector = source.track_last_sector(track_count)?;
 
-        let mb_track_info = &mb_disc_info.tracks[track_num as usize - 1];
+    // Reading from the drive is inherently sequential, but encoding is not:
+    // hand each finished track to a worker thread so that it can be encoded
+    // while the next one is being read. The channel only holds one track so
+    // that memory use stays bounded when encoding is slower than reading.
+    let (job_tx, job_rx) = mpsc::sync_channel(1);
+    let unverified_tracks = thread::scope(|scope| -> anyhow::Result<Vec<u32>> {
+        let encoder = scope.spawn(|| encode::encode_worker(encoder.as_ref(), job_rx));
 
-        let file_name = if multi_disc {
-            format!(
-                "{}-{:02} {}.flac",
-                mb_disc_info.position, track_num, mb_track_info.title
-            )
+        // The hidden track is the pre-gap of track 1, between the start of
+        // the disc and sector 0 of track 1.
+        let htoa_sectors = if args.rip_htoa && retry_tracks.is_none() {
+            source.track_first_sector(1)?
         } else {
-            format!("{:02} {}.flac", track_num, mb_track_info.title)
+            0
         };
-        let file_name = path_sanitizer.map(&file_name);
+        if args.rip_htoa && htoa_sectors == 0 {
+            warn!("Track 1 has no pre-gap; there is no hidden track to rip");
+        }
+        if htoa_sectors > 0 {
+            let file_name = ctx.htoa_file_name();
+            println!();
+            info!(
+                "Hidden track: Ripping {} sectors before track 1",
+                htoa_sectors
+            );
+            info!("Output filename: {:?}", file_name);
 
-        println!();
-        println!(
-            "Track {:02}: Ripping {} sectors ({}:{:02})",
-            track_num,
-            total_sectors,
-            track_duration / 60,
-            track_duration % 60,
-        );
-        println!("Output filename: {:?}", file_name);
-
-        let mut encoder = FlacEncoder::new()
-            .unwrap()
-            .channels(track_channels)
-            .sample_rate(CD_SAMPLE_RATE)
-            .bits_per_sample(16)
-            .init_file(&album_dir.join(&file_name))
-            .map_err(|e| anyhow!("{:?}", e))?;
-
-        let mut widen_buffer = [0i32; CD_FRAMEWORDS as usize];
-
-        paranoia.seek(SeekFrom::Start(first_sector))?;
-        for _ in first_sector..=last_sector {
-            let sector_data = paranoia.read(event_callback);
-            for (dst, src) in widen_buffer.iter_mut().zip(sector_data) {
-                *dst = (*src).into();
-            }
-            encoder
-                .process_interleaved(&widen_buffer, CD_FRAMEWORDS / track_channels)
-                .map_err(|e| anyhow!("{:?}", e))?;
-
-            if let Some(error) = paranoia.drive().errors() {
-                for line in error.to_string_lossy().lines() {
-                    println!("{}", line);
+            let window = offset::ReadWindow::new(
+                0,
+                htoa_sectors - 1,
+                args.read_offset,
+                last_readable_sector,
+            );
+            let mut samples =
+                Vec::with_capacity(window.sector_count() as usize * CD_FRAMEWORDS as usize);
+            let progress = ProgressBar::new(window.sector_count()).with_style(
+                ProgressStyle::with_template("[{elapsed_precise}] {bar:40} {pos}/{len} sectors")
+                    .unwrap(),
+            );
+            source.seek(*window.sectors().start())?;
+            for (sector, _) in (0..).zip(window.sectors()) {
+                if interrupt::interrupted() {
+                    progress.abandon();
+                    bail!(
+                        "Interrupted after sector {} of {} of the hidden track",
+                        sector,
+                        window.sector_count()
+                    );
+                }
+                source.read_sector(&mut samples)?;
+                progress.inc(1);
+            }
+            progress.finish_and_clear();
+            window.apply(&mut samples);
+            if samples.iter().all(|&sample| sample == 0) {
+                warn!("The pre-gap of track 1 is silent; it holds no hidden track");
+            }
+
+            let channels = source.track_channels(1)?;
+            let duration = samples.len() as u32 / channels / CD_SAMPLE_RATE;
+            let mut tags = album_tags.clone();
+            tags.push(("TITLE".to_string(), "Hidden Track".to_string()));
+            tags.push(("TRACKNUMBER".to_string(), "0".to_string()));
+            let job = encode::EncodeJob {
+                track_num: 0,
+                path: album_dir.join(&file_name),
+                channels,
+                samples,
+                tags,
+                cover_art: embedded_cover_art,
+            };
+            if job_tx.send(job).is_ok() {
+                playlist_entries.push(playlist::Entry {
+                    path: album_dir.join(&file_name),
+                    duration,
+                    artist: selected_release.artist_string(),
+                    title: "Hidden Track".to_string(),
+                });
+                track_reports.push(report::Track {
+                    track: 0,
+                    file_name: file_name.clone(),
+                    duration,
+                    sectors: htoa_sectors,
+                    errors: None,
+                });
+                ripped_files.push(file_name.clone());
+                htoa_file = Some(file_name);
+            }
+        }
+
+        for track_num in 1..=track_count {
+            if retry_tracks
+                .as_ref()
+                .is_some_and(|retry_tracks| !retry_tracks.contains(&track_num))
+            {
+                continue;
+            }
+            if args
+                .tracks
+                .as_ref()
+                .is_some_and(|tracks| !tracks.contains(&track_num))
+            {
+                continue;
+            }
+            if !audio_tracks.contains(&track_num) {
+                warn!("Track {} is not an audio track; skipping", track_num);
+                continue;
+            }
+
+            let start_time = Instant::now();
+
+            let first_sector = source.track_first_sector(track_num)?;
+            let last_sector = source.track_last_sector(track_num)?;
+            let total_sectors = last_sector - first_sector + 1;
+            let track_channels = source.track_channels(track_num)?;
+            let track_duration =
+                total_sectors as u32 * CD_FRAMEWORDS / (CD_SAMPLE_RATE * track_channels);
+
+            // Tracks cut with --split-at-silence are only named once the disc
+            // has been read.
+            let file_name = (!args.split_at_silence).then(|| ctx.track_file_name(track_num));
+
+            if let Some(file_name) = file_name.as_ref().filter(|_| args.resume) {
+                let path = album_dir.join(file_name);
+                if path.exists() {
+                    if ctx.encoder.is_complete(&path) {
+                        println!();
+                        info!("Track {:02}: already exists, skipping", track_num);
+                        playlist_entries.push(ctx.playlist_entry(
+                            ctx.medium.tracks.get(track_num as usize - 1),
+                            track_num,
+                            file_name.clone(),
+                            track_duration,
+                        ));
+                        continue;
+                    }
+                    info!(
+                        "Track {:02}: existing file is incomplete, ripping it again",
+                        track_num
+                    );
+                }
+            }
+
+            // Duration of the track as listed on the release, in seconds.
+            let mb_duration = ctx
+                .medium
+                .tracks
+                .get(track_num as usize - 1)
+                .and_then(|track| track.length)
+                .map(|length| (length + 500) / 1000);
+
+            println!();
+            match mb_duration {
+                Some(mb_duration) => info!(
+                    "Track {:02}: Ripping {} sectors ({}:{:02}, MusicBrainz: {}:{:02})",
+                    track_num,
+                    total_sectors,
+                    track_duration / 60,
+                    track_duration % 60,
+                    mb_duration / 60,
+                    mb_duration % 60,
+                ),
+                None => info!(
+                    "Track {:02}: Ripping {} sectors ({}:{:02})",
+                    track_num,
+                    total_sectors,
+                    track_duration / 60,
+                    track_duration % 60,
+                ),
+            }
+            if let Some(file_name) = &file_name {
+                info!("Output filename: {:?}", file_name);
+            }
+
+            let window = offset::ReadWindow::new(
+                first_sector,
+                last_sector,
+                args.read_offset,
+                last_readable_sector,
+            );
+            let mut samples =
+                Vec::with_capacity(window.sector_count() as usize * CD_FRAMEWORDS as usize);
+
+            match config.track_paranoia_mode(track_num) {
+                Some(mode) => {
+                    info!("Using paranoia mode {:?} for this track", mode);
+                    paranoia::apply_paranoia_mode(source.as_mut(), mode)?;
+                }
+                None => paranoia::apply_paranoia_mode(source.as_mut(), &args.paranoia_mode)?,
+            }
+
+            let progress = ProgressBar::new(window.sector_count()).with_style(
+                ProgressStyle::with_template(
+                    "[{elapsed_precise}] {bar:40} {pos}/{len} sectors (ETA {eta}) {msg}",
+                )
+                .unwrap(),
+            );
+            source.seek(*window.sectors().start())?;
+            paranoia::take_event_counts();
+            for (sector, _) in (0..).zip(window.sectors()) {
+                if interrupt::interrupted() {
+                    progress.abandon();
+                    bail!(
+                        "Interrupted after sector {} of {} of track {}",
+                        sector,
+                        window.sector_count(),
+                        track_num
+                    );
+                }
+                source.read_sector(&mut samples)?;
+                progress.inc(1);
+                progress.set_message(format!(
+                    "{:.1}x",
+                    rip_speed(progress.position(), start_time.elapsed().as_secs_f32())
+                ));
+            }
+            progress.finish_and_clear();
+            window.apply(&mut samples);
+            let events = paranoia::take_event_counts();
+            track_events.insert(track_num, events);
+
+            let rip_duration = start_time.elapsed().as_secs_f32();
+            let speedup = rip_speed(window.sector_count(), rip_duration);
+
+            info!("Track {:02}: {}", track_num, events.summary());
+            info!("Elapsed: {:.1} sec ({:.1}x)", rip_duration, speedup);
+            if events.errors() > 0 {
+                warn!(
+                    "{} read error(s) were corrected or skipped",
+                    events.errors()
+                );
+            }
+
+            if let Some(mb_duration) = mb_duration {
+                if track_duration.abs_diff(mb_duration) > 5 {
+                    warn!(
+                        "Ripped duration differs from MusicBrainz by {} sec; \
+                        this may be a different pressing than the selected release",
+                        track_duration.abs_diff(mb_duration)
+                    );
+                }
+            }
+
+            let track_crc = crc::track_crc(&samples);
+            info!("CRC32: {:08X}", track_crc);
+            if track_channels == 2 {
+                let checksums = accuraterip::compute(&samples, track_num, last_audio_track);
+                info!(
+                    "AccurateRip: v1 {:08X}, v2 {:08X}",
+                    checksums.v1, checksums.v2
+                );
+                accuraterip_checksums.insert(track_num, checksums);
+            }
+            match reference_crcs
+                .as_ref()
+                .and_then(|crcs| crcs.get(&track_num))
+            {
+                Some(&reference_crc) if reference_crc == track_crc => {
+                    info!("CRC matches reference");
+                }
+                Some(&reference_crc) => {
+                    warn!("CRC does not match reference ({:08X})", reference_crc);
+                    match crc::find_offset(&samples, track_channels as usize, reference_crc) {
+                        Some(offset) => warn!(
+                            "CRC matches reference when shifted by {:+} samples; \
+                            the drive offsets may differ",
+                            offset
+                        ),
+                        None => warn!("No read offset reconciles the CRCs"),
+                    }
+                    failed_tracks.push(track_num);
+                }
+                None if reference_crcs.is_some() => {
+                    warn!("Track {} is not in the reference CRC file", track_num);
+                }
+                None => {}
+            }
+
+            let mut tags = album_tags.clone();
+            let preemphasis = source.track_preemphasis(track_num)?;
+            if preemphasis {
+                info!("Track {:02} has pre-emphasis", track_num);
+                tags.push(("PREEMPHASIS".to_string(), "detected".to_string()));
+            }
+            let track = ctx.medium.tracks.get(track_num as usize - 1);
+            if let Some(track) = track {
+                tags.extend(tags::track_tags(track, track_num));
+            }
+            tags.extend(tags::genre_tag(selected_release, track, args.genre_count));
+            tags.extend(tags::track_number_tags(track_num, ctx.medium.tracks.len()));
+            let apply_deemphasis = !args.no_deemphasis
+                && match args.deemphasis {
+                    Deemphasis::Auto => preemphasis,
+                    Deemphasis::Always => true,
+                    Deemphasis::Never => false,
+                };
+            if apply_deemphasis {
+                deemphasis::deemphasize(&mut samples, track_channels as usize);
+            }
+
+            let Some(file_name) = file_name else {
+                if *disc_channels.get_or_insert(track_channels) != track_channels {
+                    bail!("Cannot split at silence: tracks have different channel counts");
                 }
+                disc_preemphasis |= preemphasis;
+                disc_samples.append(&mut samples);
+                ripped_tracks.push(track_num);
+                continue;
+            };
+
+            let job = encode::EncodeJob {
+                track_num,
+                path: album_dir.join(&file_name),
+                channels: track_channels,
+                samples,
+                tags,
+                cover_art: embedded_cover_art,
+            };
+            if job_tx.send(job).is_err() {
+                // The encoder has stopped on an error, which is reported below.
+                break;
+            }
+            ripped_tracks.push(track_num);
+            playlist_entries.push(ctx.playlist_entry(
+                ctx.medium.tracks.get(track_num as usize - 1),
+                track_num,
+                file_name.clone(),
+                track_duration,
+            ));
+            track_reports.push(report::Track {
+                track: track_num,
+                file_name: file_name.clone(),
+                duration: track_duration,
+                sectors: total_sectors,
+                errors: Some(report::Track::error_stats(&events)),
+            });
+            ripped_files.push(file_name);
+        }
+
+        if let Some(channels) = disc_channels {
+            let points =
+                silence::split_points(&disc_samples, channels as usize, args.silence_min_duration);
+            let splits = silence::split(std::mem::take(&mut disc_samples), &points);
+            if splits.len() != ctx.medium.tracks.len() {
+                warn!(
+                    "Found {} track(s) at silences but the release has {}; \
+                    numbering them instead of using MusicBrainz titles",
+                    splits.len(),
+                    ctx.medium.tracks.len()
+                );
+            }
+
+            let mut tags = album_tags.clone();
+            if disc_preemphasis {
+                tags.push(("PREEMPHASIS".to_string(), "detected".to_string()));
             }
-            if let Some(message) = paranoia.drive().messages() {
-                for line in message.to_string_lossy().lines() {
-                    println!("{}", line);
+            let split_count = splits.len();
+            for (split_num, samples) in (1..).zip(splits) {
+                let file_name = ctx.split_file_name(split_num, split_count);
+                info!("Split {:02}: {:?}", split_num, file_name);
+                let mut tags = tags.clone();
+                let track = ctx
+                    .medium
+                    .tracks
+                    .get(split_num as usize - 1)
+                    .filter(|_| split_count == ctx.medium.tracks.len());
+                if let Some(track) = track {
+                    tags.extend(tags::track_tags(track, split_num));
+                }
+                tags.extend(tags::genre_tag(selected_release, track, args.genre_count));
+                tags.extend(tags::track_number_tags(split_num, split_count));
+                let duration = samples.len() as u32 / channels / CD_SAMPLE_RATE;
+                let sectors = (samples.len() / CD_FRAMEWORDS as usize) as u64;
+                let job = encode::EncodeJob {
+                    track_num: split_num,
+                    path: album_dir.join(&file_name),
+                    channels,
+                    samples,
+                    tags,
+                    cover_art: embedded_cover_art,
+                };
+                if job_tx.send(job).is_err() {
+                    break;
                 }
+                playlist_entries.push(ctx.playlist_entry(
+                    track,
+                    split_num,
+                    file_name.clone(),
+                    duration,
+                ));
+                track_reports.push(report::Track {
+                    track: split_num,
+                    file_name: file_name.clone(),
+                    duration,
+                    sectors,
+                    errors: None,
+                });
+                ripped_files.push(file_name);
             }
         }
 
-        encoder
-            .finish()
-            .map_err(|enc| anyhow!("{:?}", enc.state()))?;
+        drop(job_tx);
+        encoder.join().expect("encoder thread panicked")
+    })?;
+
+    let mut accuraterip_mismatches = Vec::new();
+    if !accuraterip_checksums.is_empty() {
+        match verify_accuraterip(source.as_ref(), &audio_tracks, &accuraterip_checksums) {
+            Ok(mismatches) => accuraterip_mismatches = mismatches,
+            Err(error) => warn!("AccurateRip lookup failed: {:#}", error),
+        }
+    }
+
+    for track_num in ripped_tracks {
+        rip_status.tracks.insert(track_num, status::TrackStatus::Ok);
+    }
+    // CRC and AccurateRip mismatches, and files that failed verification.
+    for &track_num in failed_tracks
+        .iter()
+        .chain(&accuraterip_mismatches)
+        .chain(&unverified_tracks)
+    {
+        rip_status
+            .tracks
+            .insert(track_num, status::TrackStatus::Failed);
+    }
+    rip_status.save(&album_dir)?;
+
+    // This changes the files, so it has to happen before they are
+    // checksummed.
+    if args.replaygain && !ripped_files.is_empty() {
+        if ctx.encoder.supports_metadata() {
+            info!("Measuring ReplayGain");
+            let paths: Vec<PathBuf> = ripped_files
+                .iter()
+                .map(|file_name| album_dir.join(file_name))
+                .collect();
+            replaygain::tag_album(&paths)?;
+        } else {
+            warn!("ReplayGain cannot be tagged in this output format");
+        }
+    }
+
+    if !args.no_checksums && !ripped_files.is_empty() {
+        checksum::write_manifest(&album_dir, &ripped_files, args.checksum_format)?;
+        info!("Wrote {}", args.checksum_format.file_name());
+    }
+
+    // Tracks cut at silences don't line up with the disc's tracks, so there
+    // is nothing to describe them against.
+    if !args.split_at_silence {
+        let files: Vec<(u32, String)> = ctx
+            .track_sectors
+            .iter()
+            .filter(|&&(track_num, _)| track_num as usize <= ctx.medium.tracks.len())
+            .map(|&(track_num, _)| (track_num, ctx.track_file_name(track_num)))
+            .collect();
+        let cue_name = ctx.cue_file_name();
+        std::fs::write(
+            album_dir.join(&cue_name),
+            cue::format_cue_sheet(selected_release, mb_disc_info, htoa_file.as_deref(), &files),
+        )
+        .context("failed to write CUE sheet")?;
+        info!("Wrote {}", cue_name);
+    }
+
+    let nfo_name = ctx.nfo_file_name();
+    std::fs::write(
+        album_dir.join(&nfo_name),
+        nfo::format_nfo(
+            selected_release,
+            mb_disc_info,
+            drive_name.as_deref(),
+            args.disc_notes.as_deref(),
+            &track_events,
+            &accuraterip_checksums,
+            &mb_originals,
+        ),
+    )
+    .context("failed to write NFO")?;
+    info!("Wrote {}", nfo_name);
+
+    if !args.no_playlist && !playlist_entries.is_empty() {
+        let file_name = ctx.playlist_file_name(args.combined_playlist);
+        playlist::write(
+            &album_dir.join(&file_name),
+            &playlist_entries,
+            args.m3u_relative || !args.m3u_absolute,
+        )?;
+        info!("Wrote {}", file_name);
+    }
 
-        let end_time = Instant::now();
+    if reference_crcs.is_some() {
+        println!();
+        if failed_tracks.is_empty() {
+            info!("All compared tracks match the reference CRCs");
+        } else {
+            warn!(
+                "{} track(s) do not match the reference CRCs; \
+                retry them with --retry-failed",
+                failed_tracks.len()
+            );
+        }
+    }
 
-        let rip_duration = (end_time - start_time).as_secs_f32();
-        let speedup = track_duration as f32 / rip_duration;
+    if args.json {
+        report::print(&report::Report {
+            disc_id,
+            release_id: selected_release.id.clone(),
+            release_url: selected_release.url(),
+            album_dir,
+            tracks: track_reports,
+        })?;
+    }
 
-        println!("Elapsed: {:.1} sec ({:.1}x)", rip_duration, speedup);
+    if args.eject && args.raw_pcm_input.is_none() && !interrupt::interrupted() {
+        // Close the drive first, in case it keeps the tray locked while open.
+        drop(source);
+        if let Err(error) = drive::eject(disc_device) {
+            warn!("Failed to eject the disc: {:#}", error);
+        }
     }
 
     Ok(())
 }
 
-extern "C" fn event_callback(position: c_long, event: c_int) {
-    let _ = (position, event); //TODO
+/// Whether a track holds audio. Besides the TOC's flag, which some drives
+/// misreport for the data track of an enhanced CD, a track that has no audio
+/// channels is taken to be data.
+fn is_audio_track(source: &dyn source::AudioSource, track_num: u32) -> anyhow::Result<bool> {
+    let audio = source.track_is_audio(track_num)?;
+    let channels = source.track_channels(track_num).ok();
+    debug!(track = track_num, audio, channels, "Track format");
+    Ok(audio && channels.is_some_and(|channels| channels > 0))
+}
+
+/// Looks up the disc in the AccurateRip database and reports how each ripped
+/// track compares. Returns the tracks that do not match any submission.
+fn verify_accuraterip(
+    source: &dyn source::AudioSource,
+    audio_tracks: &BTreeSet<u32>,
+    checksums: &BTreeMap<u32, accuraterip::Checksums>,
+) -> anyhow::Result<Vec<u32>> {
+    let Some(&last_track) = audio_tracks.last() else {
+        return Ok(Vec::new());
+    };
+    let mut toc = audio_tracks
+        .iter()
+        .map(|&track_num| Ok(source.track_first_sector(track_num)? as u32))
+        .collect::<anyhow::Result<Vec<u32>>>()?;
+    toc.push(source.track_last_sector(last_track)? as u32 + 1);
+
+    println!();
+    let Some(pressings) = accuraterip::lookup(&toc)? else {
+        info!("AccurateRip: disc not in database");
+        return Ok(Vec::new());
+    };
+    let mut mismatches = Vec::new();
+    for (&track_num, &track_checksums) in checksums {
+        let Some(track_index) = audio_tracks.iter().position(|&t| t == track_num) else {
+            continue;
+        };
+        match accuraterip::verify(&pressings, track_index, track_checksums) {
+            accuraterip::Verification::Match {
+                confidence,
+                version,
+            } => info!(
+                "Track {:02}: AccurateRip matches with confidence {} (v{})",
+                track_num, confidence, version
+            ),
+            accuraterip::Verification::Mismatch { submissions: 0 } => {
+                info!("Track {:02}: not in AccurateRip database", track_num)
+            }
+            accuraterip::Verification::Mismatch { submissions } => {
+                warn!(
+                    "Track {:02}: AccurateRip does not match any of {} submissions",
+                    track_num, submissions
+                );
+                mismatches.push(track_num);
+            }
+        }
+    }
+    Ok(mismatches)
+}
+
+/// Read speed relative to playback, for `sectors` read in `seconds`.
+fn rip_speed(sectors: u64, seconds: f32) -> f32 {
+    sectors as f32 / CD_SECTORS_PER_SECOND as f32 / seconds
+}
+
+extern "C" fn event_callback(_position: c_long, event: c_int) {
+    paranoia::record_event(event);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ntfs_reserved_names() {
+        let sanitizer = PathSanitizer::ntfs();
+        assert_eq!(sanitizer.map("NUL"), "NUL_");
+        assert_eq!(sanitizer.map("com1.flac"), "com1_.flac");
+        assert_eq!(sanitizer.map("track. "), "track");
+        assert_eq!(sanitizer.map("CONCERT.flac"), "CONCERT.flac");
+    }
+
+    #[test]
+    fn truncate_at_char_boundaries() {
+        assert_eq!(
+            PathSanitizer::truncate("01 Short.flac", 200),
+            "01 Short.flac"
+        );
+        assert_eq!(PathSanitizer::truncate("ééé.flac", 8), "é.flac");
+        assert_eq!(
+            PathSanitizer::truncate("01 日本語のタイトル.flac", 20),
+            "01 日本語の.flac"
+        );
+        assert_eq!(PathSanitizer::truncate("日本語", 4), "日");
+    }
 }
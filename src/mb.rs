@@ -1,22 +1,41 @@
-use serde::{Deserialize, Deserializer};
+use std::{
+    collections::HashSet,
+    fmt, fs,
+    io::Read,
+    ops::RangeInclusive,
+    path::PathBuf,
+    sync::Mutex,
+    thread,
+    time::{Duration, Instant},
+};
 
-fn or_number<'de, D: Deserializer<'de>>(de: D) -> Result<Option<String>, D::Error> {
+use serde::{de::DeserializeOwned, Deserialize, Deserializer};
+use tracing::{debug, info, warn};
+
+/// Deserializes any JSON scalar as a string. MusicBrainz sometimes sends
+/// fields such as label codes as numbers, and occasionally as floats like
+/// `1234.0`, which are written without the fraction when it is zero.
+pub(crate) fn or_any_scalar<'de, D: Deserializer<'de>>(de: D) -> Result<Option<String>, D::Error> {
     #[derive(Deserialize)]
     #[serde(untagged)]
-    enum StringOrNumber {
+    enum Scalar {
         String(String),
-        Number(u64),
-    }
-    let v = Option::<StringOrNumber>::deserialize(de)?;
-    match v {
-        Some(StringOrNumber::String(x)) => Ok(Some(x)),
-        Some(StringOrNumber::Number(x)) => Ok(Some(x.to_string())),
-        None => Ok(None),
+        Integer(u64),
+        Float(f64),
+        Bool(bool),
     }
+    let v = Option::<Scalar>::deserialize(de)?;
+    Ok(v.map(|v| match v {
+        Scalar::String(x) => x,
+        Scalar::Integer(x) => x.to_string(),
+        Scalar::Float(x) if x.fract() == 0.0 && x.abs() < 1e15 => (x as i64).to_string(),
+        Scalar::Float(x) => x.to_string(),
+        Scalar::Bool(x) => x.to_string(),
+    }))
 }
 
-const DEFAULT_ROOT_URL: &str = "https://musicbrainz.org/ws/2";
-const DEFAULT_USER_AGENT: &str = concat!(
+pub(crate) const DEFAULT_ROOT_URL: &str = "https://musicbrainz.org/ws/2";
+pub(crate) const DEFAULT_USER_AGENT: &str = concat!(
     env!("CARGO_PKG_NAME"),
     "/",
     env!("CARGO_PKG_VERSION"),
@@ -25,19 +44,164 @@ const DEFAULT_USER_AGENT: &str = concat!(
     " )",
 );
 
+/// Shortest time between two requests allowed by the MusicBrainz API's rate
+/// limit.
+const MIN_REQUEST_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How long a request may take before it is abandoned, by default.
+pub(crate) const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Number of times a request that failed to get a response is retried, by
+/// default, waiting twice as long each time.
+pub(crate) const DEFAULT_MAX_RETRIES: u32 = 3;
+
+/// Longest wait between retries when the server is overloaded or rate
+/// limiting us.
+const MAX_BACKOFF: Duration = Duration::from_secs(64);
+
+/// Number of times a request is retried while the server is overloaded or
+/// rate limiting us, regardless of the retry limit. With the backoff, this
+/// keeps trying for about five minutes.
+const MAX_BUSY_RETRIES: u32 = 10;
+
+/// How long a cached lookup is used before it is refreshed, by default.
+pub(crate) const DEFAULT_CACHE_TTL: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+/// Number of search results looked up in full. Each lookup is a separate
+/// request, so this is kept small to stay quick under the rate limit.
+const SEARCH_LIMIT: u32 = 10;
+
+const VARIOUS_ARTISTS_ID: &str = "89ad4ac3-39f7-470e-963a-56509c546377";
+const VARIOUS_ARTISTS_NAME: &str = "Various Artists";
+
 pub struct Client {
+    agent: ureq::Agent,
     root_url: String,
     user_agent: String,
+    max_retries: u32,
+    /// Shortest time between two requests.
+    rate_limit: Duration,
+    /// Where disc ID lookups are cached, if anywhere.
+    cache_dir: Option<PathBuf>,
+    /// Whether cached lookups are ignored, and replaced by fresh ones.
+    refresh_cache: bool,
+    /// Age after which a cached lookup is replaced by a fresh one.
+    cache_ttl: Duration,
+    /// Whether cached lookups are used however old they are.
+    ignore_cache_ttl: bool,
+    last_request: Mutex<Option<Instant>>,
 }
 
-impl Client {
+/// Builds a `Client` with settings other than the defaults.
+pub struct ClientBuilder {
+    root_url: String,
+    user_agent: String,
+    timeout: Duration,
+    retry_count: u32,
+    rate_limit: Duration,
+    cache_dir: Option<PathBuf>,
+    refresh_cache: bool,
+    cache_ttl: Duration,
+    ignore_cache_ttl: bool,
+}
+
+impl ClientBuilder {
     pub fn new() -> Self {
         Self {
             root_url: DEFAULT_ROOT_URL.into(),
             user_agent: DEFAULT_USER_AGENT.into(),
+            timeout: DEFAULT_TIMEOUT,
+            retry_count: DEFAULT_MAX_RETRIES,
+            rate_limit: MIN_REQUEST_INTERVAL,
+            cache_dir: None,
+            refresh_cache: false,
+            cache_ttl: DEFAULT_CACHE_TTL,
+            ignore_cache_ttl: false,
         }
     }
 
+    pub fn root_url(mut self, root_url: impl Into<String>) -> Self {
+        self.root_url = root_url.into();
+        self
+    }
+
+    pub fn user_agent(mut self, user_agent: impl Into<String>) -> Self {
+        self.user_agent = user_agent.into();
+        self
+    }
+
+    /// How long a request may take before it is abandoned.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Number of times a request that failed to get a response is retried.
+    pub fn retry_count(mut self, retry_count: u32) -> Self {
+        self.retry_count = retry_count;
+        self
+    }
+
+    /// Shortest time between two requests. The MusicBrainz API allows one
+    /// request per second, which is the default; mirrors may allow more.
+    pub fn rate_limit(mut self, rate_limit: Duration) -> Self {
+        self.rate_limit = rate_limit;
+        self
+    }
+
+    /// Caches disc ID lookups in `cache_dir`.
+    pub fn cache_dir(mut self, cache_dir: PathBuf) -> Self {
+        self.cache_dir = Some(cache_dir);
+        self
+    }
+
+    /// Ignores cached lookups, replacing them with fresh ones.
+    pub fn refresh_cache(mut self, refresh_cache: bool) -> Self {
+        self.refresh_cache = refresh_cache;
+        self
+    }
+
+    /// Refreshes cached lookups once they are older than `cache_ttl`.
+    pub fn cache_ttl(mut self, cache_ttl: Duration) -> Self {
+        self.cache_ttl = cache_ttl;
+        self
+    }
+
+    /// Uses cached lookups however old they are, without refreshing them.
+    pub fn ignore_cache_ttl(mut self, ignore_cache_ttl: bool) -> Self {
+        self.ignore_cache_ttl = ignore_cache_ttl;
+        self
+    }
+
+    pub fn build(self) -> Client {
+        Client {
+            agent: ureq::AgentBuilder::new().timeout(self.timeout).build(),
+            root_url: self.root_url,
+            user_agent: self.user_agent,
+            max_retries: self.retry_count,
+            rate_limit: self.rate_limit,
+            cache_dir: self.cache_dir,
+            refresh_cache: self.refresh_cache,
+            cache_ttl: self.cache_ttl,
+            ignore_cache_ttl: self.ignore_cache_ttl,
+            last_request: Mutex::new(None),
+        }
+    }
+}
+
+impl Default for ClientBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Client {
+    /// A client with the default settings. Use `ClientBuilder` to change
+    /// them.
+    pub fn new() -> Self {
+        ClientBuilder::new().build()
+    }
+
     pub fn set_root_url(&mut self, root_url: String) {
         self.root_url = root_url
     }
@@ -47,31 +211,284 @@ impl Client {
     }
 
     pub fn get(&self, path_and_query: &str) -> ureq::Request {
-        ureq::get(&format!("{}/{}", self.root_url, path_and_query))
+        self.agent
+            .get(&format!("{}/{}", self.root_url, path_and_query))
             .set("User-Agent", &self.user_agent)
             .set("Accept", "application/json")
     }
+
+    /// Sends a GET request, keeping to the rate limit and retrying with
+    /// exponential backoff. Requests that get no response are retried up to
+    /// the retry limit; 503 and 429 responses are retried up to
+    /// `MAX_BUSY_RETRIES` times, and then fail with `MbError::RateLimited`.
+    fn call(&self, path_and_query: &str) -> Result<ureq::Response, MbError> {
+        let mut backoff = MIN_REQUEST_INTERVAL;
+        let mut retries = 0;
+        let mut busy_retries = 0;
+        loop {
+            self.wait_for_rate_limit();
+            match self.get(path_and_query).call() {
+                Err(ureq::Error::Status(429 | 503, _)) if busy_retries == MAX_BUSY_RETRIES => {
+                    return Err(MbError::RateLimited);
+                }
+                Err(ureq::Error::Status(status @ (429 | 503), _)) => {
+                    busy_retries += 1;
+                    warn!(
+                        "MusicBrainz responded with {}; retrying in {} sec",
+                        status,
+                        backoff.as_secs()
+                    );
+                }
+                Err(ureq::Error::Transport(error)) if retries < self.max_retries => {
+                    retries += 1;
+                    warn!(
+                        "MusicBrainz request failed: {}; retrying in {} sec",
+                        error,
+                        backoff.as_secs()
+                    );
+                }
+                result => return Ok(result?),
+            }
+            thread::sleep(backoff);
+            backoff = (backoff * 2).min(MAX_BACKOFF);
+        }
+    }
+
+    /// Sleeps until a request can be sent without exceeding the rate limit,
+    /// and records that one is being sent.
+    fn wait_for_rate_limit(&self) {
+        let mut last_request = self.last_request.lock().unwrap();
+        if let Some(last_request) = *last_request {
+            let elapsed = last_request.elapsed();
+            if elapsed < self.rate_limit {
+                thread::sleep(self.rate_limit - elapsed);
+            }
+        }
+        *last_request = Some(Instant::now());
+    }
+
+    fn get_json<T: DeserializeOwned>(&self, path_and_query: &str) -> Result<T, MbError> {
+        let response = self.call(path_and_query)?.into_reader();
+
+        let mut jd = serde_json::Deserializer::from_reader(response);
+        let response: T = serde_path_to_error::deserialize(&mut jd)?;
+        Ok(response)
+    }
+
+    /// Like `get_json`, but reads the response from `cache_name` in the cache
+    /// directory if it is there and has not expired, and stores it there
+    /// otherwise.
+    fn get_json_cached<T: DeserializeOwned>(
+        &self,
+        path_and_query: &str,
+        cache_name: &str,
+    ) -> Result<T, MbError> {
+        let Some(cache_dir) = &self.cache_dir else {
+            return self.get_json(path_and_query);
+        };
+        let cache_path = cache_dir.join(cache_name);
+        let cache_error = |action: &str, error: &dyn std::fmt::Display| {
+            MbError::Other(format!(
+                "failed to {} {}: {}",
+                action,
+                cache_path.display(),
+                error
+            ))
+        };
+
+        let cache_age = fs::metadata(&cache_path)
+            .and_then(|metadata| metadata.modified())
+            .ok()
+            .map(|modified| modified.elapsed().unwrap_or_default());
+        if let Some(age) = cache_age.filter(|_| !self.refresh_cache) {
+            let days = age.as_secs() / (24 * 60 * 60);
+            let expired = age > self.cache_ttl;
+            if !expired || self.ignore_cache_ttl {
+                if expired {
+                    info!(
+                        "Using cached MB data (age: {} days, TTL override active)",
+                        days
+                    );
+                } else {
+                    debug!("Using cached MB data (age: {} days)", days);
+                }
+                let json = fs::read(&cache_path).map_err(|e| cache_error("read", &e))?;
+                return parse_json(&json).map_err(|e| cache_error("parse", &e));
+            }
+        }
+
+        let mut json = Vec::new();
+        self.call(path_and_query)?
+            .into_reader()
+            .read_to_end(&mut json)
+            .map_err(|e| MbError::Other(format!("failed to read response: {}", e)))?;
+        let response = parse_json(&json)?;
+        fs::create_dir_all(cache_dir).map_err(|e| {
+            MbError::Other(format!("failed to create {}: {}", cache_dir.display(), e))
+        })?;
+        fs::write(&cache_path, &json).map_err(|e| cache_error("write", &e))?;
+        Ok(response)
+    }
+}
+
+fn parse_json<T: DeserializeOwned>(json: &[u8]) -> Result<T, MbError> {
+    let mut jd = serde_json::Deserializer::from_slice(json);
+    Ok(serde_path_to_error::deserialize(&mut jd)?)
+}
+
+/// Why a MusicBrainz request failed.
+#[derive(Debug)]
+pub enum MbError {
+    /// MusicBrainz has no such entity, such as an unknown disc ID.
+    NotFound,
+    /// The request could not be sent, or got an error response.
+    NetworkError(ureq::Error),
+    /// The response was not in the expected format.
+    ParseError(serde_path_to_error::Error<serde_json::Error>),
+    /// MusicBrainz refused the request because too many were sent.
+    RateLimited,
+    /// Any other failure, such as an unreadable cache file, described by the
+    /// message.
+    Other(String),
+}
+
+impl fmt::Display for MbError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::NotFound => write!(f, "not found in MusicBrainz"),
+            Self::NetworkError(error) => write!(f, "MusicBrainz request failed: {}", error),
+            Self::ParseError(error) => {
+                write!(f, "failed to parse MusicBrainz response: {}", error)
+            }
+            Self::RateLimited => write!(f, "MusicBrainz is rate limiting requests"),
+            Self::Other(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for MbError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::NetworkError(error) => Some(error),
+            Self::ParseError(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<ureq::Error> for MbError {
+    fn from(error: ureq::Error) -> Self {
+        match error {
+            ureq::Error::Status(404, _) => Self::NotFound,
+            ureq::Error::Status(429, _) => Self::RateLimited,
+            error => Self::NetworkError(error),
+        }
+    }
+}
+
+impl From<serde_path_to_error::Error<serde_json::Error>> for MbError {
+    fn from(error: serde_path_to_error::Error<serde_json::Error>) -> Self {
+        Self::ParseError(error)
+    }
 }
 
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "kebab-case")]
 pub struct DiscId {
     pub id: String,
+    /// Total number of releases with this disc ID, when the response is one
+    /// page of them.
+    #[serde(default)]
+    pub release_count: u32,
+    /// Index of the first release in this page.
+    #[serde(default)]
+    pub release_offset: u32,
     pub releases: Vec<Release>,
 }
 
+/// What disc ID lookups include by default, which the tags and file names
+/// depend on.
+pub(crate) const DEFAULT_DISC_INCLUDES: &[&str] = &[
+    "artist-credits",
+    "recordings",
+    "labels",
+    "isrcs",
+    "release-groups",
+    "tags",
+    "recording-level-rels",
+    "work-rels",
+    "work-level-rels",
+    "artist-rels",
+];
+
+/// Further includes that disc ID lookups accept.
+pub(crate) const EXTRA_DISC_INCLUDES: &[&str] = &[
+    "aliases",
+    "annotation",
+    "discids",
+    "genres",
+    "ratings",
+    "url-rels",
+    "label-rels",
+    "place-rels",
+    "release-rels",
+    "release-group-rels",
+    "series-rels",
+    "release-group-level-rels",
+];
+
 impl DiscId {
-    pub fn lookup(client: &Client, disc_id: &str) -> anyhow::Result<Self> {
-        const INCLUDES: &str = "artist-credits+recordings+labels";
+    /// Looks up the releases with `disc_id`, with the default includes.
+    pub fn lookup(client: &Client, disc_id: &str) -> Result<Self, MbError> {
+        Self::lookup_with_includes(client, disc_id, DEFAULT_DISC_INCLUDES)
+    }
 
-        let response = client
-            .get(&format!("discid/{}?inc={}", disc_id, INCLUDES))
-            .call()?
-            .into_reader();
+    /// Looks up the releases with `disc_id`, fetching further pages until
+    /// all of them are in `releases`. `includes` replaces the default
+    /// includes, so it should normally contain them.
+    pub fn lookup_with_includes(
+        client: &Client,
+        disc_id: &str,
+        includes: &[&str],
+    ) -> Result<Self, MbError> {
+        if let Some(unknown) = includes.iter().find(|include| {
+            !DEFAULT_DISC_INCLUDES.contains(include) && !EXTRA_DISC_INCLUDES.contains(include)
+        }) {
+            return Err(MbError::Other(format!(
+                "unknown MusicBrainz include {:?}",
+                unknown
+            )));
+        }
+        let includes = includes.join("+");
+        // Responses with other includes have other contents.
+        let cache_name = if includes == DEFAULT_DISC_INCLUDES.join("+") {
+            disc_id.to_string()
+        } else {
+            format!("{}+{}", disc_id, includes)
+        };
 
-        let mut jd = serde_json::Deserializer::from_reader(response);
-        let response: Self = serde_path_to_error::deserialize(&mut jd)?;
-        Ok(response)
+        let mut result: Self = client.get_json_cached(
+            &format!("discid/{}?inc={}", disc_id, includes),
+            &format!("{}.json", cache_name),
+        )?;
+        while (result.releases.len() as u32) < result.release_count {
+            let offset = result.releases.len();
+            let page: Self = client.get_json_cached(
+                &format!("discid/{}?inc={}&offset={}", disc_id, includes, offset),
+                &format!("{}-{}.json", cache_name, offset),
+            )?;
+            if page.releases.is_empty() {
+                warn!(
+                    "MusicBrainz listed {} releases for disc ID {}, but only returned {}",
+                    result.release_count,
+                    disc_id,
+                    result.releases.len()
+                );
+                break;
+            }
+            result.releases.extend(page.releases);
+        }
+        Ok(result)
     }
 }
 
@@ -91,17 +508,223 @@ pub struct Release {
     pub packaging: Option<String>,
     pub packaging_id: Option<String>,
     pub quality: String,
+    /// Only present in lookups that include `release-groups`.
+    pub release_group: Option<ReleaseGroup>,
+    pub status: Option<String>,
+    /// Only present in lookups that include `tags`.
+    #[serde(default)]
+    pub tags: Vec<MbTag>,
     pub title: String,
 }
 
 impl Release {
+    pub fn lookup(client: &Client, mbid: &str) -> Result<Self, MbError> {
+        const INCLUDES: &str = "artist-credits+recordings+labels+discids+release-groups+tags\
+            +recording-level-rels+work-rels+work-level-rels+artist-rels";
+
+        client.get_json(&format!("release/{}?inc={}", mbid, INCLUDES))
+    }
+
+    /// Searches for releases by artist name and title, for discs whose ID
+    /// is not in the database.
+    ///
+    /// Search results only carry a summary of each release, so the best
+    /// matches are looked up again in full.
+    pub fn search(client: &Client, artist: &str, title: &str) -> Result<Vec<Self>, MbError> {
+        #[derive(Deserialize)]
+        struct SearchResults {
+            releases: Vec<SearchResult>,
+        }
+
+        #[derive(Deserialize)]
+        struct SearchResult {
+            id: String,
+        }
+
+        let query = format!(
+            "artist:{} AND release:{}",
+            search_phrase(artist),
+            search_phrase(title)
+        );
+        let results: SearchResults = client.get_json(&format!(
+            "release?query={}&limit={}&fmt=json",
+            percent_encode(&query),
+            SEARCH_LIMIT
+        ))?;
+
+        results
+            .releases
+            .iter()
+            .map(|result| Self::lookup(client, &result.id))
+            .collect()
+    }
+
     pub(crate) fn artist_string(&self) -> String {
         self.artist_credit
             .iter()
-            .flat_map(|credit| [credit.name.as_str(), credit.joinphrase.as_str()])
+            .flat_map(|credit| {
+                [
+                    credit.name.as_str(),
+                    credit.joinphrase.as_deref().unwrap_or(""),
+                ]
+            })
             .collect()
     }
 
+    pub fn release_year(&self) -> Option<u32> {
+        self.date_component(0, 1..=9999)
+    }
+
+    pub fn release_month(&self) -> Option<u32> {
+        self.date_component(1, 1..=12)
+    }
+
+    pub fn release_day(&self) -> Option<u32> {
+        self.date_component(2, 1..=31)
+    }
+
+    /// Parses one component of a `YYYY`, `YYYY-MM` or `YYYY-MM-DD` date.
+    fn date_component(&self, index: usize, range: RangeInclusive<u32>) -> Option<u32> {
+        let parts: Vec<&str> = self.date.split('-').collect();
+        let well_formed = parts.len() <= 3
+            && parts.iter().zip([4, 2, 2]).all(|(part, digits)| {
+                part.len() == digits && part.bytes().all(|b| b.is_ascii_digit())
+            });
+        if !well_formed {
+            return None;
+        }
+        parts
+            .get(index)?
+            .parse()
+            .ok()
+            .filter(|value| range.contains(value))
+    }
+
+    /// The title, followed by the disambiguation comment in parentheses if
+    /// there is one, e.g. "The Dark Side of the Moon (2011 remaster)".
+    pub(crate) fn disambiguated_title(&self) -> String {
+        if self.disambiguation.is_empty() {
+            self.title.clone()
+        } else {
+            format!("{} ({})", self.title, self.disambiguation)
+        }
+    }
+
+    /// The release group's types, e.g. "Album + Live".
+    pub(crate) fn type_string(&self) -> Option<String> {
+        self.release_group.as_ref()?.type_string()
+    }
+
+    /// Like `artist_string`, but with the disambiguation comment of each
+    /// artist whose name is in `ambiguous_names`.
+    pub(crate) fn disambiguated_artist_string(&self, ambiguous_names: &HashSet<&str>) -> String {
+        self.artist_credit
+            .iter()
+            .map(|credit| {
+                let artist = &credit.artist;
+                if ambiguous_names.contains(artist.name.as_str())
+                    && !artist.disambiguation.is_empty()
+                {
+                    format!(
+                        "{} ({}){}",
+                        credit.name,
+                        artist.disambiguation,
+                        credit.joinphrase.as_deref().unwrap_or("")
+                    )
+                } else {
+                    format!(
+                        "{}{}",
+                        credit.name,
+                        credit.joinphrase.as_deref().unwrap_or("")
+                    )
+                }
+            })
+            .collect()
+    }
+
+    /// Whether the release is credited to "Various Artists", by MBID or by
+    /// name.
+    pub(crate) fn is_various_artists(&self) -> bool {
+        self.artist_credit.iter().any(|credit| {
+            credit.artist.id == VARIOUS_ARTISTS_ID
+                || credit
+                    .artist
+                    .name
+                    .eq_ignore_ascii_case(VARIOUS_ARTISTS_NAME)
+                || credit
+                    .artist
+                    .sort_name
+                    .eq_ignore_ascii_case(VARIOUS_ARTISTS_NAME)
+        })
+    }
+
+    /// Whether every track is credited to a different artist, as on
+    /// compilations that are not credited to "Various Artists" themselves.
+    fn has_distinct_track_artists(&self) -> bool {
+        let artists: Vec<String> = self
+            .media
+            .iter()
+            .flat_map(|medium| &medium.tracks)
+            .map(|track| track.artist_string())
+            .collect();
+        let distinct: HashSet<&String> = artists.iter().collect();
+        artists.len() > 1 && distinct.len() == artists.len()
+    }
+
+    /// The release's page on the MusicBrainz website.
+    pub(crate) fn url(&self) -> String {
+        format!("https://musicbrainz.org/release/{}", self.id)
+    }
+
+    /// Like `artist_string`, but with each artist's sort name, e.g.
+    /// "Beatles, The". Artists without a sort name keep their credited name.
+    pub(crate) fn sort_artist_string(&self) -> String {
+        self.artist_credit
+            .iter()
+            .flat_map(|credit| {
+                let name = if credit.artist.sort_name.is_empty() {
+                    credit.name.as_str()
+                } else {
+                    credit.artist.sort_name.as_str()
+                };
+                [name, credit.joinphrase.as_deref().unwrap_or("")]
+            })
+            .collect()
+    }
+
+    /// The artist the release as a whole is filed under: "Various Artists"
+    /// for compilations, otherwise the release's artist credit.
+    pub(crate) fn album_artist_string(&self) -> String {
+        if self.is_various_artists() || self.has_distinct_track_artists() {
+            VARIOUS_ARTISTS_NAME.to_string()
+        } else {
+            self.artist_string()
+        }
+    }
+
+    /// Names of the release's labels, joined with " / ".
+    pub(crate) fn label_string(&self) -> String {
+        self.join_label_info(|label_info| {
+            label_info.label.as_ref().map(|label| label.name.as_str())
+        })
+    }
+
+    /// Catalog numbers of the release, joined with " / ".
+    pub(crate) fn catalog_number_string(&self) -> String {
+        self.join_label_info(|label_info| label_info.catalog_number.as_deref())
+    }
+
+    fn join_label_info(&self, field: impl Fn(&LabelInfo) -> Option<&str>) -> String {
+        let mut values: Vec<&str> = self
+            .label_info
+            .iter()
+            .filter_map(field)
+            .filter(|value| !value.is_empty())
+            .collect();
+        values.dedup();
+        values.join(" / ")
+    }
+
     pub(crate) fn catalog_number(&self) -> Option<&str> {
         self.label_info
             .get(0)
@@ -109,11 +732,55 @@ impl Release {
     }
 }
 
+/// Quotes a value for a search query, so that it is matched as a phrase.
+fn search_phrase(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Escapes a query parameter for use in a URL.
+fn percent_encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct ReleaseGroup {
+    pub id: String,
+    pub primary_type: Option<String>,
+    pub primary_type_id: Option<String>,
+    #[serde(default)]
+    pub secondary_types: Vec<String>,
+    pub title: String,
+}
+
+impl ReleaseGroup {
+    /// The primary type followed by any secondary types, e.g.
+    /// "Album + Compilation, Live", or `None` if the group has no types.
+    pub(crate) fn type_string(&self) -> Option<String> {
+        let secondary = self.secondary_types.join(", ");
+        match (&self.primary_type, secondary.is_empty()) {
+            (Some(primary), true) => Some(primary.clone()),
+            (Some(primary), false) => Some(format!("{} + {}", primary, secondary)),
+            (None, false) => Some(secondary),
+            (None, true) => None,
+        }
+    }
+}
+
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "kebab-case")]
 pub struct ArtistCredit {
     pub artist: Artist,
-    pub joinphrase: String,
+    #[serde(default)]
+    pub joinphrase: Option<String>,
     pub name: String,
 }
 
@@ -124,9 +791,10 @@ pub struct Artist {
     pub id: String,
     pub name: String,
     pub sort_name: String,
+    /// Missing for artists of unknown type.
     #[serde(rename = "type")]
-    pub type_: String,
-    pub type_id: String,
+    pub type_: Option<String>,
+    pub type_id: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -143,7 +811,8 @@ pub struct CoverArtArchive {
 #[serde(rename_all = "kebab-case")]
 pub struct LabelInfo {
     pub catalog_number: Option<String>,
-    pub label: Label,
+    /// Missing for some releases that only have a catalog number.
+    pub label: Option<Label>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -151,7 +820,7 @@ pub struct LabelInfo {
 pub struct Label {
     pub disambiguation: String,
     pub id: String,
-    #[serde(deserialize_with = "or_number")]
+    #[serde(deserialize_with = "or_any_scalar")]
     pub label_code: Option<String>,
     pub name: String,
     pub sort_name: String,
@@ -160,6 +829,46 @@ pub struct Label {
     pub type_id: Option<String>,
 }
 
+impl Label {
+    pub fn lookup(client: &Client, mbid: &str) -> Result<LabelDetail, MbError> {
+        client.get_json(&format!("label/{}?inc=url-rels", mbid))
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct LabelDetail {
+    #[serde(flatten)]
+    pub label: Label,
+    #[serde(rename = "relations", default)]
+    pub url_relations: Vec<UrlRelation>,
+}
+
+impl LabelDetail {
+    pub(crate) fn official_url(&self) -> Option<&str> {
+        self.url_relations
+            .iter()
+            .find(|relation| relation.type_ == "official homepage")
+            .and_then(|relation| relation.url.as_ref())
+            .map(|url| url.resource.as_str())
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct UrlRelation {
+    #[serde(rename = "type")]
+    pub type_: String,
+    pub url: Option<Url>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Url {
+    pub id: String,
+    pub resource: String,
+}
+
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "kebab-case")]
 pub struct Media {
@@ -179,24 +888,246 @@ pub struct Disc {
     pub offset_count: u32,
     pub id: String,
     pub offsets: Vec<u32>,
+    /// Lead-out offset, in sectors.
+    pub sectors: u32,
 }
 
-#[derive(Debug, Deserialize)]
+impl Disc {
+    /// Computes the CDDB/freedb disc ID from the disc's TOC.
+    pub fn freedb_id(&self) -> u32 {
+        crate::cddb::disc_id(&self.offsets, self.sectors)
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
 #[serde(rename_all = "kebab-case")]
 pub struct Track {
     pub artist_credit: Vec<ArtistCredit>,
     pub id: String,
+    pub length: Option<u32>,
     pub number: String,
     pub position: u32,
     pub recording: Recording,
     pub title: String,
 }
 
-#[derive(Debug, Deserialize)]
+impl Track {
+    /// Stands in for a track that the disc has but MusicBrainz does not list.
+    pub(crate) fn placeholder(track_num: u32) -> Self {
+        Self {
+            number: track_num.to_string(),
+            position: track_num,
+            title: format!("Track {:02}", track_num),
+            ..Self::default()
+        }
+    }
+
+    pub(crate) fn artist_string(&self) -> String {
+        self.artist_credit
+            .iter()
+            .flat_map(|credit| {
+                [
+                    credit.name.as_str(),
+                    credit.joinphrase.as_deref().unwrap_or(""),
+                ]
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
 #[serde(rename_all = "kebab-case")]
 pub struct Recording {
     pub artist_credit: Vec<ArtistCredit>,
     pub disambiguation: String,
     pub id: String,
+    /// Only present in lookups that include `isrcs`.
+    #[serde(default)]
+    pub isrcs: Vec<String>,
+    /// Only present in lookups that include `recording-level-rels`, and then
+    /// only the relation types that were included too.
+    #[serde(default)]
+    pub relations: Vec<RecordingRelation>,
+    /// Only present in lookups that include `tags`.
+    #[serde(default)]
+    pub tags: Vec<MbTag>,
     pub title: String,
 }
+
+impl Recording {
+    /// Names of the composers of the works this recording performs, without
+    /// repeats.
+    pub(crate) fn composers(&self) -> Vec<&str> {
+        let mut composers = Vec::new();
+        let relations = self
+            .relations
+            .iter()
+            .filter_map(|relation| relation.work.as_ref())
+            .flat_map(|work| &work.relations)
+            .filter(|relation| relation.relation_type == "composer");
+        for relation in relations {
+            if let Some(artist) = &relation.artist {
+                if !composers.contains(&artist.name.as_str()) {
+                    composers.push(artist.name.as_str());
+                }
+            }
+        }
+        composers
+    }
+}
+
+/// A relationship from a recording, such as the work it is a performance of.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct RecordingRelation {
+    #[serde(rename = "type")]
+    pub relation_type: String,
+    /// Only present for relations to works.
+    pub work: Option<Work>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Work {
+    pub id: String,
+    pub title: String,
+    /// Only present in lookups that include `work-level-rels`.
+    #[serde(default)]
+    pub relations: Vec<WorkArtistRelation>,
+}
+
+/// A relationship from a work, such as to its composer or lyricist.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct WorkArtistRelation {
+    #[serde(rename = "type")]
+    pub relation_type: String,
+    /// Only present for relations to artists.
+    pub artist: Option<RelatedArtist>,
+}
+
+/// An artist as given in a relationship, which leaves out some of the fields
+/// of `Artist`.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct RelatedArtist {
+    pub id: String,
+    pub name: String,
+    pub sort_name: String,
+}
+
+/// A folksonomy tag, which is how MusicBrainz users record genres.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct MbTag {
+    pub name: String,
+    /// Number of users who applied the tag.
+    pub count: i32,
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+
+    use super::*;
+
+    /// A release with the given fields, and placeholders for the required
+    /// ones that are not given.
+    fn release(fields: Value) -> Release {
+        let mut json = json!({
+            "artist-credit": [],
+            "date": "",
+            "disambiguation": "",
+            "id": "b84ee12a-09ef-421b-82de-0441a926375b",
+            "label-info": [],
+            "media": [],
+            "quality": "normal",
+            "title": "Title",
+        });
+        for (key, value) in fields.as_object().unwrap() {
+            json[key] = value.clone();
+        }
+        serde_json::from_value(json).unwrap()
+    }
+
+    #[test]
+    fn any_scalar_as_string() {
+        #[derive(Deserialize)]
+        struct Field {
+            #[serde(deserialize_with = "or_any_scalar")]
+            value: Option<String>,
+        }
+        let parse = |json: &str| {
+            serde_json::from_str::<Field>(&format!(r#"{{"value": {}}}"#, json))
+                .unwrap()
+                .value
+        };
+
+        assert_eq!(parse(r#""LC 1234""#).as_deref(), Some("LC 1234"));
+        assert_eq!(parse("1234").as_deref(), Some("1234"));
+        assert_eq!(parse("1234.0").as_deref(), Some("1234"));
+        assert_eq!(parse("12.5").as_deref(), Some("12.5"));
+        assert_eq!(parse("true").as_deref(), Some("true"));
+        assert_eq!(parse("null"), None);
+    }
+
+    #[test]
+    fn label_info_without_label() {
+        let release = release(json!({
+            "label-info": [{ "catalog-number": "SELF-001", "label": null }],
+        }));
+        assert!(release.label_info[0].label.is_none());
+        assert_eq!(release.catalog_number(), Some("SELF-001"));
+        assert_eq!(release.catalog_number_string(), "SELF-001");
+        assert_eq!(release.label_string(), "");
+    }
+
+    #[test]
+    fn disambiguation_comments() {
+        let remaster = release(json!({
+            "title": "Nevermind",
+            "disambiguation": "2011 remaster",
+            "artist-credit": [{
+                "artist": {
+                    "disambiguation": "US rock band",
+                    "id": "5b11f4ce-a62d-471e-81fc-a69a8278c7da",
+                    "name": "Nirvana",
+                    "sort-name": "Nirvana",
+                },
+                "joinphrase": "",
+                "name": "Nirvana",
+            }],
+        }));
+        assert_eq!(remaster.disambiguated_title(), "Nevermind (2011 remaster)");
+        assert_eq!(
+            remaster.disambiguated_artist_string(&HashSet::from(["Nirvana"])),
+            "Nirvana (US rock band)"
+        );
+        assert_eq!(
+            remaster.disambiguated_artist_string(&HashSet::new()),
+            "Nirvana"
+        );
+
+        let original = release(json!({ "title": "Nevermind" }));
+        assert_eq!(original.disambiguated_title(), "Nevermind");
+    }
+
+    #[test]
+    fn release_date_components() {
+        let release_date = |date: &str| {
+            let release = release(json!({ "date": date }));
+            (
+                release.release_year(),
+                release.release_month(),
+                release.release_day(),
+            )
+        };
+
+        assert_eq!(release_date("1994"), (Some(1994), None, None));
+        assert_eq!(release_date("1994-09"), (Some(1994), Some(9), None));
+        assert_eq!(release_date("1994-09-27"), (Some(1994), Some(9), Some(27)));
+        for date in ["", "19x5", "1994-9-27", "1994-09-27-01"] {
+            assert_eq!(release_date(date), (None, None, None), "{:?}", date);
+        }
+    }
+}
@@ -0,0 +1,198 @@
+use std::{collections::BTreeMap, fmt::Write};
+
+use crate::{
+    accuraterip,
+    edit::MbOriginal,
+    mb::{Media, Release},
+    paranoia::EventCounts,
+    tags,
+};
+
+/// Width of the labels in the release summary.
+const LABEL_WIDTH: usize = 16;
+
+/// Width of the title column in the track listing.
+const TITLE_WIDTH: usize = 48;
+
+/// Formats a plain-text NFO describing the release and how each track of
+/// `media` was ripped. `events` holds the cdparanoia events of each track
+/// ripped in this run; other tracks are listed without them, as are tracks
+/// missing from `accuraterip`. `notes` is the user's note about the disc, and
+/// `mb_originals` holds the MusicBrainz titles and artists of tracks edited
+/// before ripping.
+pub fn format_nfo(
+    release: &Release,
+    media: &Media,
+    drive: Option<&str>,
+    notes: Option<&str>,
+    events: &BTreeMap<u32, EventCounts>,
+    accuraterip: &BTreeMap<u32, accuraterip::Checksums>,
+    mb_originals: &[MbOriginal],
+) -> String {
+    let mut out = String::new();
+
+    let mut field = |label: &str, value: &str| {
+        if !value.is_empty() {
+            writeln!(out, "{:<width$}{}", label, value, width = LABEL_WIDTH).unwrap();
+        }
+    };
+    field("Artist:", &release.artist_string());
+    field("Title:", &release.title);
+    field("Date:", &release.date);
+    let label_info = release.label_info.first();
+    field(
+        "Label:",
+        label_info
+            .and_then(|label_info| label_info.label.as_ref())
+            .map_or("", |label| label.name.as_str()),
+    );
+    field(
+        "Catalog number:",
+        release.catalog_number().unwrap_or_default(),
+    );
+    field("Barcode:", release.barcode.as_deref().unwrap_or_default());
+    field(
+        "Disc:",
+        &format!("{} of {}", media.position, release.media.len()),
+    );
+    field("MusicBrainz ID:", &release.id);
+    field("URL:", &release.url());
+    field("Drive:", drive.unwrap_or_default());
+    field(
+        "Ripped with:",
+        &format!("ripoff {}", env!("CARGO_PKG_VERSION")),
+    );
+
+    if let Some(notes) = notes {
+        writeln!(out).unwrap();
+        writeln!(out, "Notes:").unwrap();
+        for line in notes.lines() {
+            writeln!(out, "  {}", line).unwrap();
+        }
+    }
+
+    writeln!(out).unwrap();
+    writeln!(
+        out,
+        "{:>2}  {:<title_width$}  {:>6}  {:>6}",
+        "#",
+        "Title",
+        "Length",
+        "Errors",
+        title_width = TITLE_WIDTH
+    )
+    .unwrap();
+    for (track_num, track) in (1..).zip(&media.tracks) {
+        let title: String = tags::resolve_track_title(track, track_num)
+            .chars()
+            .take(TITLE_WIDTH)
+            .collect();
+        let length = track
+            .length
+            .map(|length| {
+                let seconds = (length + 500) / 1000;
+                format!("{}:{:02}", seconds / 60, seconds % 60)
+            })
+            .unwrap_or_default();
+        let error_count = events
+            .get(&track_num)
+            .map(|events| events.errors().to_string())
+            .unwrap_or_else(|| "-".to_string());
+        writeln!(
+            out,
+            "{:>2}  {:<title_width$}  {:>6}  {:>6}",
+            track_num,
+            title,
+            length,
+            error_count,
+            title_width = TITLE_WIDTH
+        )
+        .unwrap();
+    }
+
+    if !events.is_empty() {
+        writeln!(out).unwrap();
+        writeln!(out, "Sector errors:").unwrap();
+        for (track_num, events) in events {
+            writeln!(out, "{:>2}  {}", track_num, events.summary()).unwrap();
+        }
+    }
+
+    if !accuraterip.is_empty() {
+        writeln!(out).unwrap();
+        writeln!(out, "AccurateRip checksums:").unwrap();
+        for (track_num, checksums) in accuraterip {
+            writeln!(
+                out,
+                "{:>2}  v1 {:08X}  v2 {:08X}",
+                track_num, checksums.v1, checksums.v2
+            )
+            .unwrap();
+        }
+    }
+
+    if !mb_originals.is_empty() {
+        writeln!(out).unwrap();
+        writeln!(out, "Edited before ripping; MusicBrainz has:").unwrap();
+        for original in mb_originals {
+            writeln!(
+                out,
+                "{:>2}  {} / {}",
+                original.track_num, original.title, original.artist
+            )
+            .unwrap();
+        }
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn format(notes: Option<&str>) -> String {
+        let release: Release = serde_json::from_value(json!({
+            "artist-credit": [],
+            "date": "1991-09-24",
+            "disambiguation": "",
+            "id": "b84ee12a-09ef-421b-82de-0441a926375b",
+            "label-info": [],
+            "media": [],
+            "quality": "normal",
+            "title": "Nevermind",
+        }))
+        .unwrap();
+        let media: Media = serde_json::from_value(json!({
+            "format": "CD",
+            "format-id": "9712d52a-4509-3d4b-a1a2-67c88c643e31",
+            "position": 1,
+            "title": "",
+            "track-count": 0,
+            "track-offset": 0,
+            "discs": [],
+            "tracks": [],
+        }))
+        .unwrap();
+        format_nfo(
+            &release,
+            &media,
+            None,
+            notes,
+            &BTreeMap::new(),
+            &BTreeMap::new(),
+            &[],
+        )
+    }
+
+    #[test]
+    fn disc_notes() {
+        let nfo = format(Some("Scratch on the label side\nObi missing"));
+        assert!(nfo.contains("\nNotes:\n  Scratch on the label side\n  Obi missing\n"));
+        assert!(nfo.find("Notes:") < nfo.find("Length"));
+
+        assert!(!format(None).contains("Notes:"));
+    }
+}
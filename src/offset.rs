@@ -0,0 +1,157 @@
+use std::ops::RangeInclusive;
+
+use cdparanoia::CD_FRAMEWORDS;
+
+/// Interleaved 16-bit words in one sector, as a signed count for offset math.
+const SECTOR_WORDS: i64 = CD_FRAMEWORDS as i64;
+
+/// Audio on a CD is always stereo, so one sample of read offset is two words.
+const WORDS_PER_SAMPLE: i64 = 2;
+
+/// The sectors to read for a track so that its audio comes out where it
+/// actually is on the disc, correcting for the drive's read offset.
+///
+/// A drive with a positive offset reads ahead, so the track's audio starts
+/// `read_offset` samples into the data it returns for the track's first
+/// sector. The window covers the sectors spanning the shifted track and
+/// `apply` cuts the track back out of them.
+pub struct ReadWindow {
+    /// Position of the track's first word in the drive's addressing, which
+    /// may be before sector 0.
+    start_word: i64,
+    /// Length of the track in words.
+    len: usize,
+    sectors: RangeInclusive<u64>,
+}
+
+impl ReadWindow {
+    /// `last_readable_sector` is the last sector before the lead-out. Parts
+    /// of the window that fall outside the disc are padded with silence.
+    pub fn new(
+        first_sector: u64,
+        last_sector: u64,
+        read_offset: i32,
+        last_readable_sector: u64,
+    ) -> Self {
+        let shift = read_offset as i64 * WORDS_PER_SAMPLE;
+        let start_word = first_sector as i64 * SECTOR_WORDS + shift;
+        let end_word = (last_sector as i64 + 1) * SECTOR_WORDS + shift;
+
+        let first_read = start_word.div_euclid(SECTOR_WORDS).max(0) as u64;
+        let last_read = (end_word - 1)
+            .div_euclid(SECTOR_WORDS)
+            .min(last_readable_sector as i64);
+        // An empty range if the whole window is past the end of the disc.
+        let sectors = match u64::try_from(last_read) {
+            Ok(last_read) => first_read..=last_read,
+            Err(_) => RangeInclusive::new(1, 0),
+        };
+
+        Self {
+            start_word,
+            len: (end_word - start_word) as usize,
+            sectors,
+        }
+    }
+
+    /// The sectors to read, in order.
+    pub fn sectors(&self) -> RangeInclusive<u64> {
+        self.sectors.clone()
+    }
+
+    pub fn sector_count(&self) -> u64 {
+        self.sectors.clone().count() as u64
+    }
+
+    /// Turns the samples read from `sectors()` into the track's samples,
+    /// discarding the excess at either end and padding with silence where
+    /// the window ran off the disc.
+    pub fn apply(&self, samples: &mut Vec<i16>) {
+        let read_start = *self.sectors.start() as i64 * SECTOR_WORDS;
+        if read_start > self.start_word {
+            let padding = (read_start - self.start_word) as usize;
+            samples.splice(0..0, std::iter::repeat_n(0, padding));
+        } else {
+            let excess = ((self.start_word - read_start) as usize).min(samples.len());
+            samples.drain(..excess);
+        }
+        samples.resize(self.len, 0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Last sector of the test disc, which has 10 sectors.
+    const LAST_SECTOR: u64 = 9;
+
+    /// Reads `window` from a disc whose words hold their position on the
+    /// disc plus one, so that 0 is only ever padding.
+    fn read(window: &ReadWindow) -> Vec<i16> {
+        let mut samples = Vec::new();
+        for sector in window.sectors() {
+            let start = sector as i64 * SECTOR_WORDS;
+            samples.extend((start..start + SECTOR_WORDS).map(|word| word as i16 + 1));
+        }
+        window.apply(&mut samples);
+        samples
+    }
+
+    /// The words at positions `from..to` of the test disc.
+    fn words(from: i16, to: i16) -> Vec<i16> {
+        (from + 1..=to).collect()
+    }
+
+    fn silence(len: usize) -> Vec<i16> {
+        vec![0; len]
+    }
+
+    #[test]
+    fn zero_offset() {
+        let first = ReadWindow::new(0, 3, 0, LAST_SECTOR);
+        assert_eq!(first.sectors(), 0..=3);
+        assert_eq!(read(&first), words(0, 4704));
+
+        let last = ReadWindow::new(6, 9, 0, LAST_SECTOR);
+        assert_eq!(last.sectors(), 6..=9);
+        assert_eq!(read(&last), words(7056, 11760));
+    }
+
+    #[test]
+    fn positive_offset() {
+        // 30 samples are 60 words.
+        let first = ReadWindow::new(0, 3, 30, LAST_SECTOR);
+        assert_eq!(first.sectors(), 0..=4);
+        assert_eq!(read(&first), words(60, 4764));
+
+        // The end of the last track would be in the lead-out.
+        let last = ReadWindow::new(6, 9, 30, LAST_SECTOR);
+        assert_eq!(last.sectors(), 6..=9);
+        assert_eq!(read(&last), [words(7116, 11760), silence(60)].concat());
+    }
+
+    #[test]
+    fn negative_offset() {
+        // The start of the first track would be before sector 0.
+        let first = ReadWindow::new(0, 3, -30, LAST_SECTOR);
+        assert_eq!(first.sectors(), 0..=3);
+        assert_eq!(read(&first), [silence(60), words(0, 4644)].concat());
+
+        let last = ReadWindow::new(6, 9, -30, LAST_SECTOR);
+        assert_eq!(last.sectors(), 5..=9);
+        assert_eq!(read(&last), words(6996, 11700));
+    }
+
+    #[test]
+    fn outside_the_disc() {
+        let past_lead_out = ReadWindow::new(10, 12, 0, LAST_SECTOR);
+        assert_eq!(past_lead_out.sector_count(), 0);
+        assert_eq!(read(&past_lead_out), silence(3 * 1176));
+
+        // An offset of a whole sector back from sector 0.
+        let before_start = ReadWindow::new(0, 0, -1176, LAST_SECTOR);
+        assert_eq!(before_start.sector_count(), 0);
+        assert_eq!(read(&before_start), silence(1176));
+    }
+}
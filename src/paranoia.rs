@@ -0,0 +1,128 @@
+use std::{
+    ffi::c_int,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use anyhow::bail;
+use cdparanoia::ParanoiaMode;
+
+use crate::source::AudioSource;
+
+// Callback events from cdda_paranoia.h. Each callback reports a single event,
+// numbered from 0 to PARANOIA_CB_READERR.
+const PARANOIA_CB_FIXUP_EDGE: usize = 2;
+const PARANOIA_CB_FIXUP_ATOM: usize = 3;
+const PARANOIA_CB_SCRATCH: usize = 4;
+const PARANOIA_CB_REPAIR: usize = 5;
+const PARANOIA_CB_SKIP: usize = 6;
+const PARANOIA_CB_DRIFT: usize = 7;
+const PARANOIA_CB_FIXUP_DROPPED: usize = 10;
+const PARANOIA_CB_FIXUP_DUPED: usize = 11;
+const PARANOIA_CB_READERR: usize = 12;
+
+const EVENT_TYPES: usize = PARANOIA_CB_READERR + 1;
+
+/// Events that mean a read went wrong and had to be corrected, or could not
+/// be.
+const ERROR_EVENTS: &[usize] = &[
+    PARANOIA_CB_SCRATCH,
+    PARANOIA_CB_SKIP,
+    PARANOIA_CB_FIXUP_DROPPED,
+    PARANOIA_CB_FIXUP_DUPED,
+    PARANOIA_CB_READERR,
+];
+
+/// Events reported by cdparanoia since the last call to `take_event_counts`,
+/// by type. The read callback has no way to carry state, so this has to be
+/// global.
+static EVENT_COUNTS: [AtomicU64; EVENT_TYPES] = [const { AtomicU64::new(0) }; EVENT_TYPES];
+
+/// Counts a callback event from cdparanoia.
+pub fn record_event(event: c_int) {
+    if let Some(count) = usize::try_from(event)
+        .ok()
+        .and_then(|event| EVENT_COUNTS.get(event))
+    {
+        count.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Returns the events recorded since the last call, and starts counting
+/// again from zero.
+pub fn take_event_counts() -> EventCounts {
+    EventCounts(std::array::from_fn(|event| {
+        EVENT_COUNTS[event].swap(0, Ordering::Relaxed)
+    }))
+}
+
+/// Number of cdparanoia events of each type while reading a track.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct EventCounts([u64; EVENT_TYPES]);
+
+impl EventCounts {
+    /// Number of reads that went wrong and had to be corrected, or could not
+    /// be.
+    pub fn errors(&self) -> u64 {
+        ERROR_EVENTS.iter().map(|&event| self.0[event]).sum()
+    }
+
+    /// Counts of the events that say something about the condition of the
+    /// disc, with a plural name for each.
+    pub fn counts(&self) -> [(u64, &'static str); 8] {
+        [
+            (self.0[PARANOIA_CB_READERR], "read errors"),
+            (self.0[PARANOIA_CB_SKIP], "skips"),
+            (self.0[PARANOIA_CB_SCRATCH], "scratches"),
+            (self.0[PARANOIA_CB_REPAIR], "repairs"),
+            (self.0[PARANOIA_CB_DRIFT], "drift corrections"),
+            (
+                self.0[PARANOIA_CB_FIXUP_EDGE] + self.0[PARANOIA_CB_FIXUP_ATOM],
+                "jitter corrections",
+            ),
+            (self.0[PARANOIA_CB_FIXUP_DROPPED], "dropped bytes restored"),
+            (self.0[PARANOIA_CB_FIXUP_DUPED], "duplicated bytes removed"),
+        ]
+    }
+
+    /// Describes the events that say something about the condition of the
+    /// disc, e.g. "0 read errors, 0 skips, 2 drift corrections, ...".
+    pub fn summary(&self) -> String {
+        self.counts()
+            .iter()
+            .map(|(count, name)| format!("{} {}", count, name))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+/// Parses a paranoia mode name, or several joined with `+` (e.g.
+/// `overlap+verify`).
+pub fn parse_paranoia_mode(mode: &str) -> anyhow::Result<ParanoiaMode> {
+    let mut flags = ParanoiaMode::DISABLE;
+    for name in mode.split('+') {
+        flags |= match name.trim() {
+            "full" => ParanoiaMode::FULL,
+            "disable" => ParanoiaMode::DISABLE,
+            "verify" => ParanoiaMode::VERIFY,
+            "fragment" => ParanoiaMode::FRAGMENT,
+            "overlap" => ParanoiaMode::OVERLAP,
+            "scratch" => ParanoiaMode::SCRATCH,
+            "repair" => ParanoiaMode::REPAIR,
+            "neverskip" => ParanoiaMode::NEVERSKIP,
+            other => bail!("unknown paranoia mode {:?}", other),
+        };
+    }
+    Ok(flags)
+}
+
+/// Checks that `mode` is a valid paranoia mode, for use as a clap value
+/// parser.
+pub fn validate_paranoia_mode(mode: &str) -> anyhow::Result<String> {
+    parse_paranoia_mode(mode)?;
+    Ok(mode.to_string())
+}
+
+pub fn apply_paranoia_mode(source: &mut dyn AudioSource, mode: &str) -> anyhow::Result<()> {
+    source.set_mode(parse_paranoia_mode(mode)?);
+    Ok(())
+}
@@ -0,0 +1,111 @@
+use std::{
+    fmt::Write,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+
+use crate::util::relativize_path;
+
+/// One track of an extended M3U playlist.
+pub struct Entry {
+    /// Path of the track file.
+    pub path: PathBuf,
+    /// Length of the track, in seconds.
+    pub duration: u32,
+    pub artist: String,
+    pub title: String,
+}
+
+impl Entry {
+    fn write_to(&self, out: &mut String, location: &str) {
+        writeln!(
+            out,
+            "#EXTINF:{},{} - {}\n{}",
+            self.duration, self.artist, self.title, location
+        )
+        .unwrap();
+    }
+}
+
+/// How `track` is referred to from a playlist in `playlist_dir`: relative to
+/// it with `/` separators, as M3U players expect on every platform, or else
+/// as an absolute path.
+fn location(playlist_dir: &Path, track: &Path, relative: bool) -> anyhow::Result<String> {
+    let current_dir = std::env::current_dir().context("failed to get the current directory")?;
+    let track = current_dir.join(track);
+    if !relative {
+        return Ok(track.display().to_string());
+    }
+    let components: Vec<String> = relativize_path(&current_dir.join(playlist_dir), &track)
+        .components()
+        .map(|component| component.as_os_str().to_string_lossy().into_owned())
+        .collect();
+    Ok(components.join("/"))
+}
+
+/// Name of the playlist for one disc of a release, or for the whole release
+/// if `disc` is `None`.
+pub fn file_name(disc: Option<u32>) -> String {
+    match disc {
+        Some(disc) => format!("playlist-disc{}.m3u", disc),
+        None => "playlist.m3u".to_string(),
+    }
+}
+
+/// Writes `entries` to an extended M3U playlist, referring to the tracks by
+/// paths relative to the playlist if `relative` is set.
+///
+/// If the playlist already exists, as when other discs of the release or
+/// other tracks of this disc were ripped earlier, its entries are kept: the
+/// ones for files in `entries` are replaced in place and the rest of
+/// `entries` are added at the end.
+pub fn write(path: &Path, entries: &[Entry], relative: bool) -> anyhow::Result<()> {
+    let playlist_dir = path.parent().unwrap_or(Path::new(""));
+    let locations = entries
+        .iter()
+        .map(|entry| location(playlist_dir, &entry.path, relative))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    let existing = if path.exists() {
+        std::fs::read_to_string(path).with_context(|| format!("failed to read {:?}", path))?
+    } else {
+        String::new()
+    };
+
+    let mut out = String::from("#EXTM3U\n");
+    let mut written = vec![false; entries.len()];
+    let mut extinf = None;
+    for line in existing.lines() {
+        if line.starts_with("#EXTINF:") {
+            extinf = Some(line);
+            continue;
+        }
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        match locations.iter().position(|location| location == line) {
+            Some(index) => {
+                entries[index].write_to(&mut out, &locations[index]);
+                written[index] = true;
+            }
+            None => {
+                if let Some(extinf) = extinf {
+                    writeln!(out, "{}", extinf).unwrap();
+                }
+                writeln!(out, "{}", line).unwrap();
+            }
+        }
+        extinf = None;
+    }
+    for ((entry, location), _) in entries
+        .iter()
+        .zip(&locations)
+        .zip(written)
+        .filter(|(_, written)| !written)
+    {
+        entry.write_to(&mut out, location);
+    }
+
+    std::fs::write(path, out).with_context(|| format!("failed to write {:?}", path))
+}
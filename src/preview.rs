@@ -0,0 +1,144 @@
+use comfy_table::{presets::UTF8_FULL, Table};
+
+use crate::{mb::Media, source::AudioSource, status, tags, Cli, RipContext, CD_SECTORS_PER_SECOND};
+
+const BYTES_PER_SECTOR: u64 = 2352;
+
+/// Formats a tree of every file the rip described by `ctx` and `args` will
+/// create.
+pub fn preview_output_tree(ctx: &RipContext, args: &Cli) -> String {
+    let estimated_size = |sectors: u32| {
+        let size =
+            (sectors as u64 * BYTES_PER_SECTOR) as f64 * ctx.encoder.estimated_compression_ratio();
+        Some(size as u64)
+    };
+
+    let mut entries: Vec<(String, Option<u64>)> = Vec::new();
+    if args.rip_htoa && !args.retry_failed && ctx.htoa_sectors > 0 {
+        entries.push((ctx.htoa_file_name(), estimated_size(ctx.htoa_sectors)));
+    }
+    entries.extend(
+        ctx.track_sectors
+            .iter()
+            .filter(|&&(track_num, _)| track_num as usize <= ctx.medium.tracks.len())
+            .map(|&(track_num, sectors)| (ctx.track_file_name(track_num), estimated_size(sectors))),
+    );
+    if let Some(file_name) = &ctx.cover_art_file_name {
+        entries.push((file_name.clone(), None));
+    }
+    for path in &ctx.album_art_paths {
+        entries.push((path.display().to_string(), None));
+    }
+    if !args.no_checksums {
+        entries.push((args.checksum_format.file_name().to_string(), None));
+    }
+    if !args.split_at_silence {
+        entries.push((ctx.cue_file_name(), None));
+    }
+    entries.push((ctx.nfo_file_name(), None));
+    if !args.no_playlist {
+        entries.push((ctx.playlist_file_name(args.combined_playlist), None));
+    }
+    entries.push((status::FILE_NAME.to_string(), None));
+
+    let mut tree = format!("{}/", ctx.album_dir.display());
+    for (i, (name, estimated_size)) in entries.iter().enumerate() {
+        let branch = if i + 1 == entries.len() {
+            "└── "
+        } else {
+            "├── "
+        };
+        tree.push('\n');
+        tree.push_str(branch);
+        tree.push_str(name);
+        if let Some(size) = estimated_size {
+            tree.push_str(&format!(" (~{:.1} MB)", *size as f64 / 1_000_000.0));
+        }
+    }
+    tree
+}
+
+/// Prints where each track of the rip described by `ctx` would be written,
+/// for `--dry-run`. With `resume`, tracks that are already complete are
+/// marked as skipped.
+pub fn print_dry_run(ctx: &RipContext, resume: bool) {
+    if ctx.album_dir.exists() {
+        println!("Album directory (exists): {}", ctx.album_dir.display());
+    } else {
+        println!("Album directory: {}", ctx.album_dir.display());
+    }
+    for &(track_num, _) in &ctx.track_sectors {
+        if track_num as usize > ctx.medium.tracks.len() {
+            continue;
+        }
+        let file_name = ctx.track_file_name(track_num);
+        let path = ctx.album_dir.join(&file_name);
+        if resume && path.exists() && ctx.encoder.is_complete(&path) {
+            println!(
+                "Track {:02}: {} (exists, would be skipped)",
+                track_num, file_name
+            );
+        } else {
+            println!("Track {:02}: {}", track_num, file_name);
+        }
+    }
+}
+
+/// Prints the tags each track of the rip described by `ctx` would be tagged
+/// with, for `--test-disc-id`. Tags that depend on reading the disc, such as
+/// pre-emphasis, are left out.
+pub fn print_track_tags(ctx: &RipContext, album_tags: &[(String, String)], genre_count: usize) {
+    for &(track_num, _) in &ctx.track_sectors {
+        let Some(track) = ctx.medium.tracks.get(track_num as usize - 1) else {
+            continue;
+        };
+        let mut tags = album_tags.to_vec();
+        tags.extend(tags::track_tags(track, track_num));
+        tags.extend(tags::genre_tag(ctx.release, Some(track), genre_count));
+        tags.extend(tags::track_number_tags(track_num, ctx.medium.tracks.len()));
+        println!("Track {:02} tags:", track_num);
+        for (key, value) in &tags {
+            println!("  {}={}", key, value);
+        }
+    }
+}
+
+/// Prints the tracks about to be ripped, so that the user can check them
+/// against the disc before the rip starts. The sector counts come from
+/// `source`, which is the drive or a `--raw-pcm-input` dump.
+pub fn print_track_table(media: &Media, source: &dyn AudioSource) -> anyhow::Result<()> {
+    let disc_tracks = source.tracks()?;
+
+    let mut table = Table::new();
+    table.load_preset(UTF8_FULL).set_header([
+        "#",
+        "Title",
+        "Artist",
+        "Duration",
+        "Sectors",
+        "Recording MBID",
+    ]);
+    for (track_num, track) in (1..).zip(&media.tracks) {
+        let sectors = if track_num <= disc_tracks {
+            source.track_last_sector(track_num)? - source.track_first_sector(track_num)? + 1
+        } else {
+            0
+        };
+        let seconds = sectors / CD_SECTORS_PER_SECOND as u64;
+        let mut title = tags::resolve_track_title(track, track_num).into_owned();
+        if !track.recording.disambiguation.is_empty() {
+            title = format!("{} ({})", title, track.recording.disambiguation);
+        }
+        table.add_row([
+            track_num.to_string(),
+            title,
+            track.artist_string(),
+            format!("{}:{:02}", seconds / 60, seconds % 60),
+            sectors.to_string(),
+            track.recording.id.clone(),
+        ]);
+    }
+
+    println!("{}", table);
+    Ok(())
+}
@@ -0,0 +1,96 @@
+//! ReplayGain 2.0 tagging, measured with EBU R128.
+//!
+//! The album gain depends on every track, so this runs in two passes once
+//! the whole album has been encoded: first every file is decoded and
+//! measured, then every file is tagged with its own gain and the album's.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use ebur128::{EbuR128, Mode};
+use tracing::warn;
+
+use crate::{verify, CD_SAMPLE_RATE};
+
+/// Loudness that ReplayGain 2.0 brings tracks to, in LUFS.
+const REFERENCE_LOUDNESS: f64 = -18.0;
+
+/// Measurements of one file.
+struct Analysis<'a> {
+    path: &'a Path,
+    meter: EbuR128,
+    /// Highest sample, relative to full scale.
+    peak: f64,
+}
+
+/// Tags each native FLAC file in `paths` with its track gain and peak, and
+/// with the gain and peak of all of them together as an album.
+pub fn tag_album(paths: &[PathBuf]) -> anyhow::Result<()> {
+    let analyses = paths
+        .iter()
+        .map(|path| analyze(path))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    let album_loudness =
+        EbuR128::loudness_global_multiple(analyses.iter().map(|analysis| &analysis.meter))?;
+    let album_peak = analyses
+        .iter()
+        .map(|analysis| analysis.peak)
+        .fold(0.0, f64::max);
+
+    for analysis in &analyses {
+        let track_loudness = analysis.meter.loudness_global()?;
+        let mut tag = metaflac::Tag::read_from_path(analysis.path)?;
+        set_gain(
+            &mut tag,
+            "TRACK",
+            track_loudness,
+            analysis.peak,
+            analysis.path,
+        );
+        set_gain(&mut tag, "ALBUM", album_loudness, album_peak, analysis.path);
+        tag.save()
+            .with_context(|| format!("failed to tag {}", analysis.path.display()))?;
+    }
+
+    Ok(())
+}
+
+/// Decodes a file and measures its loudness and peak.
+fn analyze(path: &Path) -> anyhow::Result<Analysis<'_>> {
+    let channels = metaflac::Tag::read_from_path(path)?
+        .get_streaminfo()
+        .with_context(|| format!("{} has no STREAMINFO block", path.display()))?
+        .num_channels as u32;
+    let samples = verify::decode_flac(path, false)?;
+
+    let mut meter = EbuR128::new(channels, CD_SAMPLE_RATE, Mode::I | Mode::SAMPLE_PEAK)?;
+    meter.add_frames_i16(&samples)?;
+    let mut peak = 0.0;
+    for channel in 0..channels {
+        peak = f64::max(peak, meter.sample_peak(channel)?);
+    }
+
+    Ok(Analysis { path, meter, peak })
+}
+
+/// Sets the REPLAYGAIN_{scope}_GAIN and _PEAK comments, unless the audio is
+/// too quiet to measure.
+fn set_gain(tag: &mut metaflac::Tag, scope: &str, loudness: f64, peak: f64, path: &Path) {
+    if !loudness.is_finite() {
+        warn!(
+            "{}: too quiet to measure {} loudness; not tagging its ReplayGain",
+            path.display(),
+            scope.to_lowercase()
+        );
+        return;
+    }
+    tag.set_vorbis(
+        format!("REPLAYGAIN_{}_GAIN", scope),
+        vec![format!("{:.2} dB", REFERENCE_LOUDNESS - loudness)],
+    );
+    tag.set_vorbis(
+        format!("REPLAYGAIN_{}_PEAK", scope),
+        vec![format!("{:.6}", peak)],
+    );
+}
@@ -0,0 +1,85 @@
+//! The machine-readable summary of a rip printed with `--json`.
+
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::Write,
+    os::fd::FromRawFd,
+    path::PathBuf,
+    sync::{Mutex, OnceLock},
+};
+
+use anyhow::{bail, Context};
+use serde::Serialize;
+
+use crate::paranoia::EventCounts;
+
+/// The original stdout, once `redirect_stdout` has sent everything else to
+/// stderr.
+static REPORT_OUTPUT: OnceLock<Mutex<File>> = OnceLock::new();
+
+#[derive(Serialize)]
+pub struct Report {
+    pub disc_id: String,
+    pub release_id: String,
+    pub release_url: String,
+    pub album_dir: PathBuf,
+    pub tracks: Vec<Track>,
+}
+
+#[derive(Serialize)]
+pub struct Track {
+    pub track: u32,
+    pub file_name: String,
+    /// In seconds.
+    pub duration: u32,
+    pub sectors: u64,
+    /// cdparanoia events while reading the track, keyed by name, e.g.
+    /// `read_errors`. Tracks cut with `--split-at-silence` have none.
+    pub errors: Option<BTreeMap<String, u64>>,
+}
+
+impl Track {
+    pub fn error_stats(events: &EventCounts) -> BTreeMap<String, u64> {
+        events
+            .counts()
+            .iter()
+            .map(|&(count, name)| (name.replace(' ', "_"), count))
+            .collect()
+    }
+}
+
+/// Sends everything printed to stdout to stderr from now on, keeping the
+/// original stdout for `print` so that it holds nothing but the report.
+pub fn redirect_stdout() -> anyhow::Result<()> {
+    std::io::stdout().flush()?;
+    // SAFETY: only file descriptors 1 and 2 are touched, and the duplicate of
+    // 1 is owned by nothing else.
+    let report_output = unsafe {
+        let fd = libc::dup(libc::STDOUT_FILENO);
+        if fd < 0 || libc::dup2(libc::STDERR_FILENO, libc::STDOUT_FILENO) < 0 {
+            bail!(
+                "failed to redirect stdout: {}",
+                std::io::Error::last_os_error()
+            );
+        }
+        File::from_raw_fd(fd)
+    };
+    if REPORT_OUTPUT.set(Mutex::new(report_output)).is_err() {
+        bail!("stdout was already redirected");
+    }
+    Ok(())
+}
+
+/// Prints the report as one line of JSON to the stdout saved by
+/// `redirect_stdout`.
+pub fn print(report: &Report) -> anyhow::Result<()> {
+    let mut output = REPORT_OUTPUT
+        .get()
+        .context("stdout was not redirected")?
+        .lock()
+        .unwrap();
+    serde_json::to_writer(&mut *output, report)?;
+    writeln!(output)?;
+    Ok(())
+}
@@ -0,0 +1,100 @@
+use crate::CD_SAMPLE_RATE;
+
+/// RMS level below which a window counts as silence, about -50 dBFS.
+const SILENCE_THRESHOLD: f64 = 32768.0 * 0.003;
+
+/// Number of frames (samples per channel) the RMS level is measured over:
+/// one sector.
+const WINDOW_FRAMES: usize = 588;
+
+/// Finds the places to split a recording at stretches of silence lasting at
+/// least `min_duration_ms`.
+///
+/// Returns the index into `samples` at which each new track starts, always on
+/// a frame boundary and in the middle of the silent stretch. Silence at the
+/// very start or end of the recording does not produce a split.
+pub fn split_points(samples: &[i16], channels: usize, min_duration_ms: u32) -> Vec<usize> {
+    let window_len = WINDOW_FRAMES * channels;
+    let min_windows = (min_duration_ms as usize * CD_SAMPLE_RATE as usize / 1000)
+        .div_ceil(WINDOW_FRAMES)
+        .max(1);
+    let windows: Vec<bool> = samples.chunks(window_len).map(is_silent).collect();
+
+    let mut points = Vec::new();
+    let mut run_start = None;
+    for (i, &silent) in windows.iter().enumerate() {
+        match (silent, run_start) {
+            (true, None) => run_start = Some(i),
+            (false, Some(start)) => {
+                run_start = None;
+                if start > 0 && i - start >= min_windows {
+                    points.push((start + i) / 2 * window_len);
+                }
+            }
+            _ => {}
+        }
+    }
+    points
+}
+
+fn is_silent(window: &[i16]) -> bool {
+    let sum_squares: f64 = window.iter().map(|&s| (s as f64) * (s as f64)).sum();
+    (sum_squares / window.len() as f64).sqrt() < SILENCE_THRESHOLD
+}
+
+/// Cuts `samples` into consecutive tracks at the given split points.
+pub fn split(mut samples: Vec<i16>, points: &[usize]) -> Vec<Vec<i16>> {
+    let mut tracks = Vec::with_capacity(points.len() + 1);
+    for &point in points.iter().rev() {
+        tracks.push(samples.split_off(point));
+    }
+    tracks.push(samples);
+    tracks.reverse();
+    tracks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A stereo recording of one window per element, loud or silent.
+    fn recording(loud: &[bool]) -> Vec<i16> {
+        loud.iter()
+            .flat_map(|&loud| [if loud { 10000 } else { 0 }; WINDOW_FRAMES * 2])
+            .collect()
+    }
+
+    #[test]
+    fn split_in_the_middle_of_silence() {
+        // 40 ms is three windows.
+        let samples = recording(&[true, false, false, false, false, true]);
+        let points = split_points(&samples, 2, 40);
+        assert_eq!(points, [3 * WINDOW_FRAMES * 2]);
+
+        let tracks = split(samples, &points);
+        assert_eq!(tracks.len(), 2);
+        assert_eq!(tracks[0].len(), 3 * WINDOW_FRAMES * 2);
+        assert_eq!(tracks[1].len(), 3 * WINDOW_FRAMES * 2);
+    }
+
+    #[test]
+    fn short_silence_is_not_split() {
+        let samples = recording(&[true, false, false, true]);
+        assert!(split_points(&samples, 2, 40).is_empty());
+    }
+
+    #[test]
+    fn silence_at_the_ends_is_not_split() {
+        let samples = recording(&[false, false, false, true, false, false, false]);
+        assert!(split_points(&samples, 2, 40).is_empty());
+    }
+
+    #[test]
+    fn split_at_points() {
+        let samples = (0..10).collect();
+        assert_eq!(
+            split(samples, &[3, 7]),
+            [vec![0, 1, 2], vec![3, 4, 5, 6], vec![7, 8, 9]]
+        );
+    }
+}
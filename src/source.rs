@@ -0,0 +1,194 @@
+use std::{
+    fs::File,
+    io::{BufReader, Read, Seek, SeekFrom},
+    path::Path,
+};
+
+use anyhow::{bail, Context};
+use cdparanoia::{CdromParanoia, ParanoiaMode, CD_FRAMEWORDS};
+use tracing::warn;
+
+use crate::{event_callback, log_drive_messages, mb};
+
+/// Bytes of raw PCM in one sector.
+const SECTOR_BYTES: usize = CD_FRAMEWORDS as usize * 2;
+
+/// Length of the lead-in before sector 0. TOC offsets from MusicBrainz count
+/// it, but the sector numbers used for reading do not.
+pub(crate) const LEAD_IN_SECTORS: u32 = 150;
+
+/// Where the audio of a rip is read from, one sector at a time.
+pub trait AudioSource {
+    fn tracks(&self) -> anyhow::Result<u32>;
+
+    /// Whether the track holds audio rather than data.
+    fn track_is_audio(&self, track: u32) -> anyhow::Result<bool>;
+
+    fn track_first_sector(&self, track: u32) -> anyhow::Result<u64>;
+
+    fn track_last_sector(&self, track: u32) -> anyhow::Result<u64>;
+
+    fn track_channels(&self, track: u32) -> anyhow::Result<u32>;
+
+    /// Whether the TOC flags the track as pre-emphasized.
+    fn track_preemphasis(&self, track: u32) -> anyhow::Result<bool>;
+
+    /// Sets how carefully sectors are verified, for sources that can re-read.
+    fn set_mode(&mut self, mode: ParanoiaMode);
+
+    fn seek(&mut self, sector: u64) -> anyhow::Result<()>;
+
+    /// Reads the next sector, appending it to `samples` as interleaved 16-bit
+    /// samples.
+    fn read_sector(&mut self, samples: &mut Vec<i16>) -> anyhow::Result<()>;
+}
+
+impl AudioSource for CdromParanoia {
+    fn tracks(&self) -> anyhow::Result<u32> {
+        Ok(self.drive().tracks()?)
+    }
+
+    fn track_is_audio(&self, track: u32) -> anyhow::Result<bool> {
+        Ok(self.drive().track_audiop(track)?)
+    }
+
+    fn track_first_sector(&self, track: u32) -> anyhow::Result<u64> {
+        Ok(self.drive().track_first_sector(track)?)
+    }
+
+    fn track_last_sector(&self, track: u32) -> anyhow::Result<u64> {
+        Ok(self.drive().track_last_sector(track)?)
+    }
+
+    fn track_channels(&self, track: u32) -> anyhow::Result<u32> {
+        Ok(self.drive().track_channels(track)?)
+    }
+
+    fn track_preemphasis(&self, track: u32) -> anyhow::Result<bool> {
+        Ok(self.drive().track_preemp(track)?)
+    }
+
+    fn set_mode(&mut self, mode: ParanoiaMode) {
+        CdromParanoia::set_mode(self, mode);
+    }
+
+    fn seek(&mut self, sector: u64) -> anyhow::Result<()> {
+        CdromParanoia::seek(self, SeekFrom::Start(sector))?;
+        Ok(())
+    }
+
+    fn read_sector(&mut self, samples: &mut Vec<i16>) -> anyhow::Result<()> {
+        samples.extend_from_slice(self.read(event_callback));
+        log_drive_messages(self.drive());
+        Ok(())
+    }
+}
+
+/// A dump of the whole disc as raw PCM: interleaved signed 16-bit
+/// little-endian stereo samples at 44.1 kHz, starting at the first track.
+///
+/// The file has no TOC of its own, so the track boundaries are taken from the
+/// MusicBrainz disc matching it.
+pub struct RawPcm {
+    file: BufReader<File>,
+    /// TOC offset of each track, in sectors.
+    offsets: Vec<u32>,
+    /// Lead-out offset, in sectors.
+    leadout: u32,
+}
+
+impl RawPcm {
+    pub fn open(path: &Path, disc: &mb::Disc) -> anyhow::Result<Self> {
+        let file =
+            File::open(path).with_context(|| format!("failed to open {}", path.display()))?;
+        let Some(&first_offset) = disc.offsets.first() else {
+            bail!("MusicBrainz disc {} has no tracks", disc.id);
+        };
+
+        let expected_len = (disc.sectors - first_offset) as u64 * SECTOR_BYTES as u64;
+        let len = file.metadata()?.len();
+        if len != expected_len {
+            warn!(
+                "{} is {} bytes, but the disc's TOC describes {} bytes of audio",
+                path.display(),
+                len,
+                expected_len
+            );
+        }
+
+        Ok(Self {
+            file: BufReader::new(file),
+            offsets: disc.offsets.clone(),
+            leadout: disc.sectors,
+        })
+    }
+
+    fn track_offset(&self, track: u32) -> anyhow::Result<u32> {
+        match track.checked_sub(1) {
+            Some(index) if (index as usize) < self.offsets.len() => {
+                Ok(self.offsets[index as usize])
+            }
+            _ => bail!("track {} is not on the disc", track),
+        }
+    }
+}
+
+impl AudioSource for RawPcm {
+    fn tracks(&self) -> anyhow::Result<u32> {
+        Ok(self.offsets.len() as u32)
+    }
+
+    fn track_is_audio(&self, track: u32) -> anyhow::Result<bool> {
+        self.track_offset(track)?;
+        Ok(true)
+    }
+
+    fn track_first_sector(&self, track: u32) -> anyhow::Result<u64> {
+        Ok((self.track_offset(track)? - LEAD_IN_SECTORS) as u64)
+    }
+
+    fn track_last_sector(&self, track: u32) -> anyhow::Result<u64> {
+        self.track_offset(track)?;
+        let next_offset = self
+            .offsets
+            .get(track as usize)
+            .copied()
+            .unwrap_or(self.leadout);
+        Ok((next_offset - LEAD_IN_SECTORS - 1) as u64)
+    }
+
+    fn track_channels(&self, track: u32) -> anyhow::Result<u32> {
+        self.track_offset(track)?;
+        Ok(2)
+    }
+
+    fn track_preemphasis(&self, track: u32) -> anyhow::Result<bool> {
+        self.track_offset(track)?;
+        Ok(false)
+    }
+
+    fn set_mode(&mut self, _mode: ParanoiaMode) {}
+
+    fn seek(&mut self, sector: u64) -> anyhow::Result<()> {
+        let first_sector = (self.offsets[0] - LEAD_IN_SECTORS) as u64;
+        let Some(relative) = sector.checked_sub(first_sector) else {
+            bail!("sector {} is before the start of the raw PCM input", sector);
+        };
+        self.file
+            .seek(SeekFrom::Start(relative * SECTOR_BYTES as u64))?;
+        Ok(())
+    }
+
+    fn read_sector(&mut self, samples: &mut Vec<i16>) -> anyhow::Result<()> {
+        let mut bytes = [0; SECTOR_BYTES];
+        self.file
+            .read_exact(&mut bytes)
+            .context("raw PCM input ended before the last track")?;
+        samples.extend(
+            bytes
+                .chunks_exact(2)
+                .map(|sample| i16::from_le_bytes([sample[0], sample[1]])),
+        );
+        Ok(())
+    }
+}
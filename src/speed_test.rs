@@ -0,0 +1,56 @@
+use std::{io::SeekFrom, time::Instant};
+
+use anyhow::bail;
+use cdparanoia::ParanoiaMode;
+use tracing::warn;
+
+use crate::{event_callback, log_drive_messages, open_drive};
+
+/// Sectors per second read by a drive running at 1x (normal playback) speed.
+const SECTORS_PER_SECOND_1X: f64 = 75.0;
+
+/// Reads `sectors` sectors from the middle of the disc and reports how fast
+/// the drive delivered them, with the drive limited to `max_speed` if given.
+pub fn run(disc_device: &str, sectors: u32, max_speed: Option<u32>) -> anyhow::Result<()> {
+    let mut paranoia = open_drive(disc_device, max_speed)?;
+    // Measure the drive itself, not paranoia's verification overhead.
+    paranoia.set_mode(ParanoiaMode::DISABLE);
+
+    let track_count = paranoia.drive().tracks()?;
+    let disc_first_sector = paranoia.drive().disc_first_sector()?;
+    let disc_last_sector = paranoia.drive().track_last_sector(track_count)?;
+    let disc_sectors = disc_last_sector - disc_first_sector + 1;
+    if disc_sectors < sectors as u64 {
+        bail!(
+            "Disc only has {} sectors, cannot read {}",
+            disc_sectors,
+            sectors
+        );
+    }
+    let start_sector = disc_first_sector + (disc_sectors - sectors as u64) / 2;
+
+    println!(
+        "Reading {} sectors starting at sector {}",
+        sectors, start_sector
+    );
+
+    paranoia.seek(SeekFrom::Start(start_sector))?;
+    let start_time = Instant::now();
+    for _ in 0..sectors {
+        paranoia.read(event_callback);
+        log_drive_messages(paranoia.drive());
+    }
+    let elapsed = start_time.elapsed().as_secs_f64();
+
+    let sectors_per_second = sectors as f64 / elapsed;
+    let speed = sectors_per_second / SECTORS_PER_SECOND_1X;
+    println!(
+        "Read speed: {:.1} sectors/sec ({:.1}x) in {:.1} sec",
+        sectors_per_second, speed, elapsed
+    );
+    if speed < 1.0 {
+        warn!("Drive is reading slower than 1x; cdparanoia may have difficulty ripping");
+    }
+
+    Ok(())
+}
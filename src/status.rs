@@ -0,0 +1,52 @@
+use std::{collections::BTreeMap, path::Path};
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+
+/// Name of the status file kept in each album directory.
+pub const FILE_NAME: &str = ".ripoff-status.json";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TrackStatus {
+    Ok,
+    Failed,
+}
+
+/// Outcome of each track ripped into an album directory, so that a later run
+/// can retry only the tracks that failed.
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct RipStatus {
+    #[serde(default)]
+    pub tracks: BTreeMap<u32, TrackStatus>,
+}
+
+impl RipStatus {
+    /// Loads the status file from `album_dir`, or an empty status if there is
+    /// none.
+    pub fn load(album_dir: &Path) -> anyhow::Result<Self> {
+        let path = album_dir.join(FILE_NAME);
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let contents = std::fs::read_to_string(&path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        serde_json::from_str(&contents)
+            .with_context(|| format!("failed to parse {}", path.display()))
+    }
+
+    pub fn save(&self, album_dir: &Path) -> anyhow::Result<()> {
+        let path = album_dir.join(FILE_NAME);
+        let contents = serde_json::to_string_pretty(self)?;
+        std::fs::write(&path, contents)
+            .with_context(|| format!("failed to write {}", path.display()))
+    }
+
+    pub fn failed_tracks(&self) -> Vec<u32> {
+        self.tracks
+            .iter()
+            .filter(|(_, &status)| status == TrackStatus::Failed)
+            .map(|(&track_num, _)| track_num)
+            .collect()
+    }
+}
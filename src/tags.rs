@@ -0,0 +1,202 @@
+use std::borrow::Cow;
+
+use tracing::warn;
+
+use crate::mb::{LabelDetail, MbTag, Media, Release, Track};
+
+/// Builds the Vorbis comments shared by every track of a release.
+pub fn release_tags(release: &Release) -> Vec<(String, String)> {
+    let mut tags = vec![
+        ("ALBUM".to_string(), release.title.clone()),
+        ("ALBUMARTIST".to_string(), release.album_artist_string()),
+        ("MUSICBRAINZ_ALBUMID".to_string(), release.id.clone()),
+    ];
+    for credit in &release.artist_credit {
+        tags.push((
+            "MUSICBRAINZ_ALBUMARTISTID".to_string(),
+            credit.artist.id.clone(),
+        ));
+    }
+    if let Some(release_group) = &release.release_group {
+        tags.push((
+            "MUSICBRAINZ_RELEASEGROUPID".to_string(),
+            release_group.id.clone(),
+        ));
+    }
+
+    if !release.date.is_empty() {
+        tags.push(("DATE".to_string(), release.date.clone()));
+    }
+    if let Some(year) = release.release_year() {
+        tags.push(("YEAR".to_string(), year.to_string()));
+    }
+    if let Some(country) = &release.country {
+        tags.push(("RELEASECOUNTRY".to_string(), country.clone()));
+    }
+
+    let label = release.label_string();
+    if !label.is_empty() {
+        tags.push(("LABEL".to_string(), label));
+    }
+    let catalog_number = release.catalog_number_string();
+    if !catalog_number.is_empty() {
+        tags.push(("CATALOGNUMBER".to_string(), catalog_number));
+    }
+    if let Some(barcode) = release
+        .barcode
+        .as_ref()
+        .filter(|barcode| !barcode.is_empty())
+    {
+        tags.push(("BARCODE".to_string(), barcode.clone()));
+    }
+
+    tags
+}
+
+/// Builds the Vorbis comments shared by every track of one medium of a
+/// release. Only multi-disc releases are tagged with a disc number.
+pub fn medium_tags(release: &Release, medium: &Media, disc_id: &str) -> Vec<(String, String)> {
+    let mut tags = vec![("MUSICBRAINZ_DISCID".to_string(), disc_id.to_string())];
+
+    if release.media.len() > 1 {
+        tags.push(("DISCNUMBER".to_string(), medium.position.to_string()));
+        tags.push(("DISCTOTAL".to_string(), release.media.len().to_string()));
+    }
+
+    tags
+}
+
+/// Longest disc note stored in a COMMENT tag, in characters.
+const MAX_COMMENT_LEN: usize = 500;
+
+/// Builds the COMMENT tag holding the user's note about the disc.
+pub fn comment_tag(notes: &str) -> (String, String) {
+    let comment = match notes.char_indices().nth(MAX_COMMENT_LEN) {
+        Some((end, _)) => {
+            warn!(
+                "Disc notes are longer than {} characters; truncating COMMENT tag",
+                MAX_COMMENT_LEN
+            );
+            &notes[..end]
+        }
+        None => notes,
+    };
+    ("COMMENT".to_string(), comment.to_string())
+}
+
+/// Builds the Vorbis comments describing the release's label.
+pub fn label_tags(label: &LabelDetail) -> Vec<(String, String)> {
+    let mut tags = Vec::new();
+
+    if let Some(url) = label.official_url() {
+        tags.push(("LABEL_URL".to_string(), url.to_string()));
+    }
+    if let Some(label_type) = &label.label.type_ {
+        tags.push(("LABELTYPE".to_string(), label_type.clone()));
+    }
+
+    tags
+}
+
+/// Builds the GENRE tag from the `count` most popular folksonomy tags of the
+/// track's recording, or of the release if the recording has none. Returns
+/// nothing if neither has any.
+pub fn genre_tag(
+    release: &Release,
+    track: Option<&Track>,
+    count: usize,
+) -> Option<(String, String)> {
+    let tags = track
+        .map(|track| &track.recording.tags)
+        .filter(|tags| !tags.is_empty())
+        .unwrap_or(&release.tags);
+    let mut tags: Vec<&MbTag> = tags.iter().collect();
+    tags.sort_by(|a, b| b.count.cmp(&a.count));
+    let genres: Vec<&str> = tags
+        .iter()
+        .take(count)
+        .map(|tag| tag.name.as_str())
+        .collect();
+    if genres.is_empty() {
+        return None;
+    }
+    Some(("GENRE".to_string(), genres.join(" / ")))
+}
+
+/// Builds the Vorbis comments describing one track's MusicBrainz data.
+pub fn track_tags(track: &Track, track_num: u32) -> Vec<(String, String)> {
+    let mut tags = vec![
+        (
+            "TITLE".to_string(),
+            resolve_track_title(track, track_num).into_owned(),
+        ),
+        ("ARTIST".to_string(), track.artist_string()),
+        // Following Picard, the "track ID" is the recording's, and the track
+        // itself is the "release track".
+        (
+            "MUSICBRAINZ_TRACKID".to_string(),
+            track.recording.id.clone(),
+        ),
+        ("MUSICBRAINZ_RELEASETRACKID".to_string(), track.id.clone()),
+    ];
+    for credit in &track.artist_credit {
+        tags.push(("MUSICBRAINZ_ARTISTID".to_string(), credit.artist.id.clone()));
+    }
+
+    if !track.recording.disambiguation.is_empty() {
+        tags.push((
+            "VERSION".to_string(),
+            track.recording.disambiguation.clone(),
+        ));
+    }
+    if let Some(isrc) = track.recording.isrcs.first() {
+        tags.push(("ISRC".to_string(), isrc.clone()));
+    }
+    for composer in track.recording.composers() {
+        tags.push(("COMPOSER".to_string(), composer.to_string()));
+    }
+
+    tags
+}
+
+/// Builds the Vorbis comments giving a track's position on its medium.
+pub fn track_number_tags(track_num: u32, track_total: usize) -> Vec<(String, String)> {
+    vec![
+        ("TRACKNUMBER".to_string(), track_num.to_string()),
+        ("TRACKTOTAL".to_string(), track_total.to_string()),
+    ]
+}
+
+/// Picks a usable title for a track, falling back to the recording title and
+/// then to a generic name when MusicBrainz has an empty title. The generic
+/// name is the same as `Track::placeholder`'s.
+pub fn resolve_track_title(track: &Track, track_num: u32) -> Cow<'_, str> {
+    if !track.title.is_empty() {
+        Cow::Borrowed(&track.title)
+    } else if !track.recording.title.is_empty() {
+        Cow::Borrowed(&track.recording.title)
+    } else {
+        Cow::Owned(format!("Track {:02}", track_num))
+    }
+}
+
+/// Warns about the tracks of `medium` that `resolve_track_title` has to find
+/// another title for.
+pub fn warn_empty_titles(medium: &Media) {
+    for (track_num, track) in (1..).zip(&medium.tracks) {
+        if !track.title.is_empty() {
+            continue;
+        }
+        if !track.recording.title.is_empty() {
+            warn!(
+                "Track {}: title is empty in MB, using recording title instead.",
+                track_num
+            );
+        } else {
+            warn!(
+                "Track {}: title and recording title are empty in MB, using a generic title instead.",
+                track_num
+            );
+        }
+    }
+}
@@ -0,0 +1,311 @@
+//! Templates for output file and directory names, with `{field}`
+//! placeholders:
+//!
+//! ```text
+//! {track:02} {title}
+//! {disc}-{track:03} - {artist} - {title}
+//! ```
+//!
+//! A number after a colon zero-pads a numeric field to that width. `{{` and
+//! `}}` stand for literal braces.
+//!
+//! When a field has no value, brackets left empty around it and separators
+//! left doubled next to it are removed, so that "{album} ({country})"
+//! renders as just the album.
+
+use anyhow::{bail, Context};
+
+#[derive(Clone, Debug)]
+pub struct Template {
+    parts: Vec<Part>,
+}
+
+#[derive(Clone, Debug)]
+enum Part {
+    Literal(String),
+    Field { field: Field, width: usize },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Field {
+    Track,
+    Disc,
+    Title,
+    Artist,
+    Album,
+    Year,
+    Version,
+    Country,
+    Catalog,
+}
+
+impl Field {
+    fn from_name(name: &str) -> anyhow::Result<Self> {
+        Ok(match name {
+            "track" => Self::Track,
+            "disc" => Self::Disc,
+            "title" => Self::Title,
+            "artist" => Self::Artist,
+            "album" => Self::Album,
+            "year" => Self::Year,
+            "version" => Self::Version,
+            "country" => Self::Country,
+            "catalog" => Self::Catalog,
+            _ => bail!(
+                "unknown field {{{}}} (expected track, disc, title, artist, album, year, \
+                version, country or catalog)",
+                name
+            ),
+        })
+    }
+
+    /// Whether the field describes the whole release rather than one track.
+    fn is_album_field(self) -> bool {
+        matches!(
+            self,
+            Self::Artist | Self::Album | Self::Year | Self::Country | Self::Catalog
+        )
+    }
+}
+
+/// Values substituted into a template. Fields without a value render empty.
+#[derive(Default)]
+pub struct Values<'a> {
+    pub track: Option<u32>,
+    pub disc: Option<u32>,
+    pub title: &'a str,
+    pub artist: &'a str,
+    pub album: &'a str,
+    pub year: Option<u32>,
+    pub version: &'a str,
+    pub country: &'a str,
+    pub catalog: &'a str,
+}
+
+impl Template {
+    pub fn parse(template: &str) -> anyhow::Result<Self> {
+        let mut parts = Vec::new();
+        let mut literal = String::new();
+        let mut chars = template.chars();
+
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.as_str().starts_with('{') => {
+                    chars.next();
+                    literal.push('{');
+                }
+                '}' if chars.as_str().starts_with('}') => {
+                    chars.next();
+                    literal.push('}');
+                }
+                '{' => {
+                    let (placeholder, rest) = chars
+                        .as_str()
+                        .split_once('}')
+                        .with_context(|| format!("unclosed {{ in template {:?}", template))?;
+                    let (name, width) = match placeholder.split_once(':') {
+                        Some((name, width)) => (
+                            name,
+                            width
+                                .parse()
+                                .with_context(|| format!("invalid width in {{{}}}", placeholder))?,
+                        ),
+                        None => (placeholder, 0),
+                    };
+                    if !literal.is_empty() {
+                        parts.push(Part::Literal(std::mem::take(&mut literal)));
+                    }
+                    parts.push(Part::Field {
+                        field: Field::from_name(name.trim())?,
+                        width,
+                    });
+                    chars = rest.chars();
+                }
+                '}' => bail!("unmatched }} in template {:?}", template),
+                c => literal.push(c),
+            }
+        }
+        if !literal.is_empty() {
+            parts.push(Part::Literal(literal));
+        }
+
+        Ok(Self { parts })
+    }
+
+    /// Parses a template for the album directory, which can only use the
+    /// fields describing the whole release.
+    pub fn parse_dirname(template: &str) -> anyhow::Result<Self> {
+        let parsed = Self::parse(template)?;
+        for part in &parsed.parts {
+            if let Part::Field { field, .. } = part {
+                if !field.is_album_field() {
+                    bail!(
+                        "only artist, album, year, country and catalog can be used in a \
+                        directory name: {:?}",
+                        template
+                    );
+                }
+            }
+        }
+        Ok(parsed)
+    }
+
+    pub fn render(&self, values: &Values) -> String {
+        let mut pieces: Vec<String> = self
+            .parts
+            .iter()
+            .map(|part| match part {
+                Part::Literal(literal) => literal.clone(),
+                &Part::Field { field, width } => {
+                    let number = |number: Option<u32>| {
+                        number
+                            .map(|number| format!("{:0width$}", number))
+                            .unwrap_or_default()
+                    };
+                    match field {
+                        Field::Track => number(values.track),
+                        Field::Disc => number(values.disc),
+                        Field::Year => number(values.year),
+                        Field::Title => values.title.to_string(),
+                        Field::Artist => values.artist.to_string(),
+                        Field::Album => values.album.to_string(),
+                        Field::Version => values.version.to_string(),
+                        Field::Country => values.country.to_string(),
+                        Field::Catalog => values.catalog.to_string(),
+                    }
+                }
+            })
+            .collect();
+        for (index, part) in self.parts.iter().enumerate() {
+            if matches!(part, Part::Field { .. }) && pieces[index].is_empty() {
+                collapse_empty_field(&self.parts, &mut pieces, index);
+            }
+        }
+        pieces.concat()
+    }
+}
+
+fn is_separator(c: char) -> bool {
+    matches!(c, ' ' | '-' | '_')
+}
+
+/// Fails if `name` is empty or only separators and dots, as when every field
+/// of a directory template has no value. Such a name would make the album
+/// directory its parent, the output directory, or one above it.
+pub fn check_dir_name(name: &str) -> anyhow::Result<()> {
+    if name.chars().all(|c| is_separator(c) || c == '.') {
+        bail!(
+            "the album directory name {:?} is empty for this release; use a \
+            --dirname-template with fields that it has",
+            name
+        );
+    }
+    Ok(())
+}
+
+/// Removes the brackets and the separator around the field at `index`,
+/// which rendered empty, e.g. "Artist - {year} - Album ({country})" becomes
+/// "Artist - Album". Only the literal parts of the template next to the
+/// field are changed, never the values of other fields.
+fn collapse_empty_field(parts: &[Part], pieces: &mut [String], index: usize) {
+    let is_literal = |i: usize| matches!(parts.get(i), Some(Part::Literal(_)));
+    let before = index.checked_sub(1).filter(|&i| is_literal(i));
+    let after = Some(index + 1).filter(|&i| is_literal(i));
+
+    if let (Some(before), Some(after)) = (before, after) {
+        let open = pieces[before].trim_end_matches(' ');
+        let close = pieces[after].trim_start_matches(' ');
+        if [('(', ')'), ('[', ']')]
+            .iter()
+            .any(|&(o, c)| open.ends_with(o) && close.starts_with(c))
+        {
+            let open_at = open.len() - 1;
+            let close_end = pieces[after].len() - close.len() + 1;
+            pieces[before].truncate(open_at);
+            pieces[after].replace_range(..close_end, "");
+        }
+    }
+
+    let at_start = pieces[..index].iter().all(|piece| piece.is_empty());
+    let at_end = pieces[index + 1..].iter().all(|piece| piece.is_empty());
+    match (before, after) {
+        (_, Some(after)) if at_start => {
+            let stripped =
+                pieces[after].len() - pieces[after].trim_start_matches(is_separator).len();
+            pieces[after].replace_range(..stripped, "");
+        }
+        (Some(before), after)
+            if at_end || after.is_some_and(|after| pieces[after].starts_with(is_separator)) =>
+        {
+            let kept = pieces[before].trim_end_matches(is_separator).len();
+            pieces[before].truncate(kept);
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(template: &str, values: &Values) -> String {
+        Template::parse(template).unwrap().render(values)
+    }
+
+    #[test]
+    fn empty_fields_collapse() {
+        let values = Values {
+            artist: "Artist",
+            album: "Album",
+            ..Values::default()
+        };
+        assert_eq!(
+            render("{artist} - {year} - {album}", &values),
+            "Artist - Album"
+        );
+        assert_eq!(render("{year} - {album}", &values), "Album");
+        assert_eq!(render("{album} - {year}", &values), "Album");
+        assert_eq!(render("{album} ({country})", &values), "Album");
+        assert_eq!(
+            render("{album} [{catalog}] - {artist}", &values),
+            "Album - Artist"
+        );
+        assert_eq!(
+            render("{artist} - {year} - {country} - {album}", &values),
+            "Artist - Album"
+        );
+        assert_eq!(render("{disc}-{track:02} {album}", &values), "Album");
+    }
+
+    #[test]
+    fn field_values_are_not_collapsed() {
+        let values = Values {
+            track: Some(1),
+            title: "Foo -- Bar",
+            catalog: "AB--12",
+            ..Values::default()
+        };
+        assert_eq!(
+            render("{track:02} - {title} [{catalog}] ({country})", &values),
+            "01 - Foo -- Bar [AB--12]"
+        );
+        for title in ["A__B", "Track ()", "-Intro-"] {
+            let values = Values {
+                title,
+                ..Values::default()
+            };
+            assert_eq!(render("{year} - {title}", &values), title);
+        }
+    }
+
+    #[test]
+    fn empty_dir_names() {
+        let values = Values::default();
+        for template in ["{year}", "{artist} - {year}", "[{country}] {catalog}"] {
+            let name = Template::parse_dirname(template).unwrap().render(&values);
+            assert!(check_dir_name(&name).is_err(), "{:?}", template);
+        }
+        assert!(check_dir_name("..").is_err());
+        assert!(check_dir_name("Artist - Album").is_ok());
+    }
+}
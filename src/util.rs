@@ -0,0 +1,41 @@
+use std::path::{Component, Path, PathBuf};
+
+/// The path of `to` relative to the directory `from`, such as
+/// `Disc 1/01 Track.flac` or `../01 Track.flac`. Both paths must be absolute,
+/// or relative to the same directory.
+pub fn relativize_path(from: &Path, to: &Path) -> PathBuf {
+    let from: Vec<Component> = from.components().collect();
+    let to: Vec<Component> = to.components().collect();
+    let common = from.iter().zip(&to).take_while(|(a, b)| a == b).count();
+
+    let mut path = PathBuf::new();
+    for _ in common..from.len() {
+        path.push("..");
+    }
+    for component in &to[common..] {
+        path.push(component);
+    }
+    path
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn relative_paths() {
+        let album = Path::new("/music/Artist/Album");
+        assert_eq!(
+            relativize_path(album, &album.join("01 Track.flac")),
+            Path::new("01 Track.flac")
+        );
+        assert_eq!(
+            relativize_path(album, &album.join("Disc 1/01 Track.flac")),
+            Path::new("Disc 1/01 Track.flac")
+        );
+        assert_eq!(
+            relativize_path(album, Path::new("/music/Artist/01 Track.flac")),
+            Path::new("../01 Track.flac")
+        );
+    }
+}
@@ -0,0 +1,123 @@
+//! Decoding of encoded files, to check that they hold exactly the audio that
+//! was ripped.
+
+use std::{
+    ffi::{c_void, CString},
+    fmt,
+    os::unix::ffi::OsStrExt,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, bail};
+use cdparanoia::CD_FRAMEWORDS;
+use flac_sys::*;
+
+/// Audio decoded by the write callback, and whether the decoder reported
+/// errors along the way.
+struct DecodeState {
+    samples: Vec<i16>,
+    errors: u32,
+}
+
+/// Decodes a FLAC or Ogg FLAC file into interleaved 16-bit samples.
+pub fn decode_flac(path: &Path, ogg: bool) -> anyhow::Result<Vec<i16>> {
+    let c_path = CString::new(path.as_os_str().as_bytes())?;
+    let mut state = DecodeState {
+        samples: Vec::new(),
+        errors: 0,
+    };
+
+    unsafe {
+        let decoder = FLAC__stream_decoder_new();
+        if decoder.is_null() {
+            bail!("Failed to create a decoder to verify {:?}", path);
+        }
+        let init = if ogg {
+            FLAC__stream_decoder_init_ogg_file
+        } else {
+            FLAC__stream_decoder_init_file
+        };
+        let status = init(
+            decoder,
+            c_path.as_ptr(),
+            Some(write_callback),
+            None,
+            Some(error_callback),
+            &mut state as *mut DecodeState as *mut c_void,
+        );
+        let decoded = status == FLAC__StreamDecoderInitStatus_FLAC__STREAM_DECODER_INIT_STATUS_OK
+            && FLAC__stream_decoder_process_until_end_of_stream(decoder) != 0;
+        FLAC__stream_decoder_finish(decoder);
+        FLAC__stream_decoder_delete(decoder);
+        if !decoded {
+            bail!("Failed to decode {:?} for verification", path);
+        }
+    }
+
+    if state.errors > 0 {
+        return Err(anyhow!(
+            "Decoder reported {} error(s) while verifying {:?}",
+            state.errors,
+            path
+        ));
+    }
+    Ok(state.samples)
+}
+
+/// An encoded file whose audio differs from the ripped audio.
+#[derive(Debug)]
+pub struct Mismatch {
+    pub track_num: u32,
+    pub path: PathBuf,
+    /// First and last differing sector, counted from the start of the track.
+    pub sectors: (usize, usize),
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Track {}: encoded audio does not match the ripped audio in sectors {}-{} ({:?})",
+            self.track_num, self.sectors.0, self.sectors.1, self.path
+        )
+    }
+}
+
+impl std::error::Error for Mismatch {}
+
+/// Finds the range of sectors, counted from the start of the track, in which
+/// `decoded` differs from `expected`.
+pub fn mismatched_sectors(expected: &[i16], decoded: &[i16]) -> Option<(usize, usize)> {
+    let sector = |index: usize| index / CD_FRAMEWORDS as usize;
+    let len = expected.len().max(decoded.len());
+    let differs = |&index: &usize| expected.get(index) != decoded.get(index);
+    let first = (0..len).find(differs)?;
+    let last = (0..len).rev().find(differs)?;
+    Some((sector(first), sector(last)))
+}
+
+unsafe extern "C" fn write_callback(
+    _decoder: *const FLAC__StreamDecoder,
+    frame: *const FLAC__Frame,
+    buffer: *const *const FLAC__int32,
+    client_data: *mut c_void,
+) -> FLAC__StreamDecoderWriteStatus {
+    let state = &mut *(client_data as *mut DecodeState);
+    let header = &(*frame).header;
+    let channels = std::slice::from_raw_parts(buffer, header.channels as usize);
+    for i in 0..header.blocksize as usize {
+        for &channel in channels {
+            state.samples.push(*channel.add(i) as i16);
+        }
+    }
+    FLAC__StreamDecoderWriteStatus_FLAC__STREAM_DECODER_WRITE_STATUS_CONTINUE
+}
+
+unsafe extern "C" fn error_callback(
+    _decoder: *const FLAC__StreamDecoder,
+    _status: FLAC__StreamDecoderErrorStatus,
+    client_data: *mut c_void,
+) {
+    let state = &mut *(client_data as *mut DecodeState);
+    state.errors += 1;
+}